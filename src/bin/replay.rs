@@ -0,0 +1,127 @@
+extern crate pewpew;
+
+use std::env;
+use std::io;
+use std::io::{BufRead, Write};
+use std::path::Path;
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+use pewpew::engine::json::JsonServerMessage;
+use pewpew::engine::lifecycle::Phase;
+use pewpew::engine::protocol::ServerMessage;
+use pewpew::engine::replay::{Playback, Replay};
+
+const USAGE: &'static str = "usage: replay round.replay [--speed X] [--from TICK] [--watch PLAYER] [--fast]";
+
+const FRAME_MS: u32 = 50;
+
+/// Plays a recorded round back in real time (or faster, or slower)
+/// Events are printed as they happen, with `--watch` the chosen ship's snapshots (and the
+/// events and recorded checkpoints) go to stdout as JSON lines, the same ones the server's JSON port sends
+/// While it's running, type `pause`, `play`, `speed X`, `seek TICK` or `step N`
+fn main() {
+    let mut args = env::args().skip(1);
+    let path = args.next().expect(USAGE);
+    let mut speed = 1.0;
+    let mut from = 0;
+    let mut watch = None;
+    let mut fast = false;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--speed" => speed = args.next().and_then(|s| s.parse().ok()).expect(USAGE),
+            "--from" => from = args.next().and_then(|s| s.parse().ok()).expect(USAGE),
+            "--watch" => watch = Some(args.next().and_then(|s| s.parse().ok()).expect(USAGE)),
+            "--fast" => fast = true,
+            _ => panic!("{}", USAGE),
+        }
+    }
+
+    let replay = Replay::load(Path::new(&path)).unwrap_or_else(|e| panic!("Couldn't load {}: {}", path, e));
+    let mut log = if watch.is_some() { Box::new(io::stderr()) as Box<Write> } else { Box::new(io::stdout()) };
+    writeln!(log, "{} ticks, seed {}", replay.frames.len(), replay.seed()).unwrap();
+    let mut playback = Playback::new(replay);
+    playback.set_speed(speed);
+    playback.seek(from);
+
+    let controls = read_controls();
+    let mut out = io::stdout();
+    while !playback.finished() {
+        for line in controls.try_iter() {
+            control(&mut playback, &line, &mut *log);
+        }
+        if fast {
+            let rest = playback.len();
+            playback.step(rest);
+        } else {
+            thread::sleep(Duration::from_millis(FRAME_MS as u64));
+            playback.advance(FRAME_MS);
+        }
+        let phase = if playback.finished() { Phase::Results { remaining_ms: 0 } } else { Phase::Live };
+        let events = playback.drain_events();
+        match watch {
+            Some(player) => {
+                let mut messages: Vec<ServerMessage> = events.into_iter().map(ServerMessage::Event).collect();
+                messages.extend(playback.drain_checkpoints().into_iter()
+                    .map(|check| ServerMessage::Checkpoint { time: check.time, hash: check.hash }));
+                let status = match playback.view(player) {
+                    Some(view) => ServerMessage::Snapshot { phase: phase, view: view },
+                    None => ServerMessage::Phase(phase),
+                };
+                messages.insert(0, status);
+                for message in messages {
+                    out.write_all(&JsonServerMessage::from(&message).to_line()).unwrap();
+                }
+            },
+            None => {
+                for event in events {
+                    writeln!(log, "{}ms: {:?}", playback.round().board.time(), event).unwrap();
+                }
+            },
+        }
+    }
+
+    if let Some(desync) = playback.desync() {
+        writeln!(log, "{}", desync).unwrap();
+    }
+    let round = playback.round();
+    match round.results() {
+        Some(results) => writeln!(log, "match over at {}ms, won by {:?}", results.time, results.winners).unwrap(),
+        None => writeln!(log, "recording stops at {}ms", round.board.time()).unwrap(),
+    }
+    for (player, score) in round.scoreboard.standings() {
+        writeln!(log, "player {}: {}", player, score).unwrap();
+    }
+}
+
+/// Lines typed on stdin, read on their own thread so playback doesn't wait for them
+fn read_controls() -> mpsc::Receiver<String> {
+    let (tx, rx) = mpsc::channel();
+    thread::spawn(move || {
+        let stdin = io::stdin();
+        for line in stdin.lock().lines().filter_map(|line| line.ok()) {
+            if tx.send(line).is_err() {
+                return;
+            }
+        }
+    });
+    rx
+}
+
+fn control(playback: &mut Playback, line: &str, log: &mut Write) {
+    let words: Vec<&str> = line.split_whitespace().collect();
+    match (words.get(0).cloned(), words.get(1).and_then(|s| s.parse::<f32>().ok())) {
+        (Some("pause"), _) => playback.pause(),
+        (Some("play"), _) => playback.resume(),
+        (Some("speed"), Some(speed)) => playback.set_speed(speed),
+        (Some("seek"), Some(tick)) => playback.seek(tick as usize),
+        (Some("step"), Some(ticks)) => { playback.step(ticks as usize); },
+        _ => {
+            writeln!(log, "pause, play, speed X, seek TICK or step N").unwrap();
+            return;
+        },
+    }
+    writeln!(log, "tick {} of {}{}", playback.position(), playback.len(),
+             if playback.paused() { " (paused)" } else { "" }).unwrap();
+}
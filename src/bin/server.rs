@@ -1,8 +1,137 @@
 extern crate pewpew;
+
+use std::convert::TryFrom;
+use std::env;
+use std::path::{Path, PathBuf};
+
+use pewpew::engine::bots::{Bot, Tactic};
+use pewpew::engine::engine::Round;
+use pewpew::engine::lifecycle::{Match, MatchConfig};
 use pewpew::engine::networking;
-use pewpew::engine::engine;
+use pewpew::engine::pilots;
+use pewpew::engine::script::{Budget, PilotWatcher};
+use pewpew::engine::sim::Scenario;
+use pewpew::game::board::Team;
+use pewpew::game::boundary::Boundary;
+use pewpew::game::map::Map;
+use pewpew::game::mapgen;
+use pewpew::game::mode::{FriendlyFire, GameMode, Limits};
 
+const USAGE: &'static str = "usage: server [--bots seeker,kiter,...] [--seed N] [--min-players N] \
+                             [--mode deathmatch|team|lastship] [--time-limit S] [--score-limit N] \
+                             [--teams N] [--friendly-fire off|reduced|full] [--map map.toml | --generate SEED] \
+                             [--boundary open|walls|push|zone|wrap] [--record replay-dir] \
+                             [pilot.rhai|pilot.wasm|bot-executable ...]";
+
+/// Each pilot gets its own ship, scripts are reloaded when they change
+/// Built-in bots (drifter, seeker, kiter, dodger) fly the ships after the pilots'
+/// Maps with bounds get walls unless another boundary is picked
+/// With teams, ships are spread evenly across them in player order
+/// With `--record`, every round is saved as a replay that the replay binary can play back
 fn main() {
-    let round = engine::Round::new();
-    networking::launch_server(round.board); // this blocks until the server is shut down
+    let mut paths = Vec::new();
+    let mut bots = Vec::new();
+    let mut seed: u32 = 0;
+    let mut mode = "deathmatch".to_string();
+    let mut limits = Limits::none();
+    let mut teams: Option<u8> = None;
+    let mut friendly_fire = FriendlyFire::Off;
+    let mut map = None;
+    let mut generate: Option<u32> = None;
+    let mut boundary = Boundary::Walls;
+    let mut config = MatchConfig::default();
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--bots" => {
+                let names = args.next().expect(USAGE);
+                bots = names.split(',')
+                    .map(|name| Tactic::from_name(name).unwrap_or_else(|| panic!("Unknown bot {}", name)))
+                    .collect();
+            },
+            "--seed" => seed = args.next().and_then(|s| s.parse().ok()).expect(USAGE),
+            "--min-players" => {
+                config.min_players = args.next().and_then(|s| s.parse().ok()).expect(USAGE);
+            },
+            "--mode" => mode = args.next().expect(USAGE),
+            "--time-limit" => {
+                let seconds: u32 = args.next().and_then(|s| s.parse().ok()).expect(USAGE);
+                limits.time = Some(seconds * 1000);
+            },
+            "--score-limit" => {
+                limits.score = Some(args.next().and_then(|s| s.parse().ok()).expect(USAGE));
+            },
+            "--teams" => teams = Some(args.next().and_then(|s| s.parse().ok()).expect(USAGE)),
+            "--friendly-fire" => {
+                friendly_fire = args.next().and_then(|s| FriendlyFire::from_name(&s)).expect(USAGE);
+            },
+            "--map" => {
+                let path = args.next().expect(USAGE);
+                map = Some(Map::load(Path::new(&path)).unwrap_or_else(|e| panic!("Couldn't load {}: {}", path, e)));
+            },
+            "--generate" => generate = Some(args.next().and_then(|s| s.parse().ok()).expect(USAGE)),
+            "--boundary" => boundary = args.next().and_then(|s| Boundary::from_name(&s)).expect(USAGE),
+            "--record" => config.replays = Some(PathBuf::from(args.next().expect(USAGE))),
+            _ => paths.push(arg),
+        }
+    }
+
+    let mut watcher = PilotWatcher::new(Budget::default());
+    let ships = u8::try_from(paths.len() + bots.len()).expect(USAGE);
+    let mode = match mode.as_str() {
+        "deathmatch" => GameMode::Deathmatch,
+        "team" => GameMode::TeamDeathmatch,
+        "lastship" => GameMode::LastShipStanding,
+        _ => panic!("{}", USAGE),
+    };
+    let teams = teams.unwrap_or(if mode == GameMode::TeamDeathmatch { 2 } else { 0 });
+    if let Some(seed) = generate {
+        // a side per team, or per ship when it's every ship for itself
+        let sides = if teams > 0 { teams } else { ships.max(2) };
+        let params = mapgen::Params {
+            sides: sides,
+            spawns_per_side: ((ships as usize + sides as usize - 1) / sides as usize) as u8,
+            team_spawns: teams > 0,
+            ..mapgen::Params::default()
+        };
+        map = Some(mapgen::generate(seed, &params));
+    }
+    // every round starts over from the same layout, pilots and bots carry over between rounds
+    let mut game = Match::new(config, Box::new(move || {
+        let mut round = match map {
+            Some(ref map) => {
+                let mut round = Round::new();
+                round.load_map(map.clone());
+                round.set_boundary(boundary.clone());
+                round
+            },
+            None => Scenario::ring(ships, 20.0).into_round(),
+        };
+        for (id, team) in Team::defaults(teams) {
+            round.board.add_team(id, team);
+        }
+        for player in 1..=ships {
+            round.board.join_team(player, None);
+            round.spawn_ship(player);
+        }
+        round.friendly_fire = friendly_fire;
+        round.set_mode(mode.clone(), limits.clone());
+        round
+    }));
+    // later rounds carry on from here, so `--seed` is enough to play the whole session again
+    game.round.reseed(seed);
+    println!("Seed {}", seed);
+    for (i, path) in paths.iter().enumerate() {
+        let player = i as u8 + 1;
+        let pilot = pilots::load(Path::new(path), player, Some(&mut watcher))
+            .unwrap_or_else(|e| panic!("Couldn't load pilot {}: {}", path, e));
+        game.round.add_controller(player, pilot);
+    }
+    for (i, tactic) in bots.into_iter().enumerate() {
+        let player = (paths.len() + i) as u8 + 1;
+        println!("Player {} is a {} bot", player, tactic.name());
+        let rng = game.round.rng().fork(player as u64);
+        game.round.add_controller(player, Box::new(Bot::new(tactic, rng)));
+    }
+    networking::launch_server(game, watcher); // this blocks until the server is shut down
 }
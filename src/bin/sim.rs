@@ -0,0 +1,94 @@
+extern crate pewpew;
+
+use std::convert::TryFrom;
+use std::env;
+use std::path::Path;
+
+use pewpew::engine::bots::{Bot, Tactic};
+use pewpew::engine::engine::Round;
+use pewpew::engine::pilots;
+use pewpew::engine::sim::Scenario;
+use pewpew::game::map::Map;
+use pewpew::game::mapgen;
+
+const USAGE: &'static str = "usage: sim [--ships N] [--ticks N] [--seed N] [--map map.toml | --generate SEED] \
+                             [--bots seeker,kiter,...] [pilot.rhai|pilot.wasm|bot-executable ...]";
+
+/// Runs a match as fast as it'll go and prints how it turned out
+/// Pilots get the first ships, then the bots, any ships left over just drift
+/// Without a map the ships start in a ring, burning toward the middle
+fn main() {
+    let mut ships: u8 = 2;
+    let mut ticks: u32 = 1000;
+    let mut seed: u32 = 0;
+    let mut map = None;
+    let mut generate = None;
+    let mut bots = Vec::new();
+    let mut paths = Vec::new();
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--ships" => ships = args.next().and_then(|s| s.parse().ok()).expect(USAGE),
+            "--ticks" => ticks = args.next().and_then(|s| s.parse().ok()).expect(USAGE),
+            "--seed" => seed = args.next().and_then(|s| s.parse().ok()).expect(USAGE),
+            "--map" => {
+                let path = args.next().expect(USAGE);
+                map = Some(Map::load(Path::new(&path)).unwrap_or_else(|e| panic!("Couldn't load {}: {}", path, e)));
+            },
+            "--generate" => generate = Some(args.next().and_then(|s| s.parse().ok()).expect(USAGE)),
+            "--bots" => {
+                let names = args.next().expect(USAGE);
+                bots = names.split(',')
+                    .map(|name| Tactic::from_name(name).unwrap_or_else(|| panic!("Unknown bot {}", name)))
+                    .collect();
+            },
+            _ => paths.push(arg),
+        }
+    }
+    let ships = ships.max(u8::try_from(paths.len() + bots.len()).expect(USAGE));
+    if let Some(map_seed) = generate {
+        // a side per ship, it's every ship for itself
+        let sides = ships.max(2);
+        let params = mapgen::Params { sides: sides, spawns_per_side: 1, ..mapgen::Params::default() };
+        map = Some(mapgen::generate(map_seed, &params));
+    }
+
+    let mut round = match map {
+        Some(map) => {
+            let mut round = Round::new();
+            round.load_map(map);
+            for player in 1..=ships {
+                round.spawn_ship(player);
+            }
+            round
+        },
+        None => Scenario::ring(ships, 20.0).into_round(),
+    };
+    round.reseed(seed);
+    for (i, path) in paths.iter().enumerate() {
+        let player = i as u8 + 1;
+        let pilot = pilots::load(Path::new(path), player, None)
+            .unwrap_or_else(|e| panic!("Couldn't load pilot {}: {}", path, e));
+        round.add_controller(player, pilot);
+    }
+    for (i, tactic) in bots.into_iter().enumerate() {
+        let player = (paths.len() + i) as u8 + 1;
+        println!("player {} is a {} bot", player, tactic.name());
+        let rng = round.rng().fork(player as u64);
+        round.add_controller(player, Box::new(Bot::new(tactic, rng)));
+    }
+    let report = round.fast_forward(ticks);
+
+    println!("{:#?}", round.board);
+    for event in round.drain_events() {
+        println!("{:?}", event);
+    }
+    for (player, score) in round.scoreboard.standings() {
+        println!("player {}: {}", player, score);
+    }
+    if let Some(results) = round.results() {
+        println!("match over at {}ms, won by {:?} (seed {})", results.time, results.winners, results.seed);
+    }
+    println!("{} ticks with {} ships in {:.3}s ({:.0} ticks/s)",
+             report.ticks, report.ships, report.elapsed_s, report.ticks_per_s());
+}
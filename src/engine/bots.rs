@@ -0,0 +1,312 @@
+use std::f32::consts::PI;
+
+use na::Vector3;
+use rand::Rng;
+
+use game::autopilot::Autopilot;
+use game::board::Timestep;
+use game::controller::{Commands, Contact, Fault, SensorView, ShipController};
+use game::rng::GameRng;
+use game::scanner::Scanner;
+use game::ship::MAX_THRUST;
+use game::targeting::{lead, steer};
+
+/// Fire when the target is this close to dead ahead, as the cosine of the angle off the nose
+const FIRING_CONE: f32 = 0.995;
+/// Speed the seeker plans its ramming runs around
+const RAM_SPEED: f32 = 15.0;
+/// How far away the kiter keeps its target, comfortably inside beam range
+const KITE_RANGE: f32 = 60.0;
+/// How long the dodger sticks with each jink, in ms
+const JINK_MS: Timestep = 500;
+/// The dodger backs off from anything closer than this
+const PERSONAL_SPACE: f32 = 30.0;
+
+/// How a built-in bot flies
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Tactic {
+    /// Does nothing at all, a moving target
+    Drifter,
+    /// Leads the nearest ship and rams it, shooting on the way in
+    Seeker,
+    /// Circles the nearest ship at a distance, shooting whenever it lines up
+    Kiter,
+    /// Jinks at random across the nearest ship's line of fire, shooting back when it can
+    Dodger,
+}
+
+impl Tactic {
+    pub fn from_name(name: &str) -> Option<Tactic> {
+        match name {
+            "drifter" => Some(Tactic::Drifter),
+            "seeker" => Some(Tactic::Seeker),
+            "kiter" => Some(Tactic::Kiter),
+            "dodger" => Some(Tactic::Dodger),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Tactic::Drifter => "drifter",
+            Tactic::Seeker => "seeker",
+            Tactic::Kiter => "kiter",
+            Tactic::Dodger => "dodger",
+        }
+    }
+}
+
+/// A built-in opponent, flying through the same interface as scripts and remote players
+/// A dodger's jinks come from a generator forked off the round's, forked again whenever the bot
+/// joins a round or the round is reseeded, so a round flown by bots plays out the same from its seed
+pub struct Bot {
+    tactic: Tactic,
+    rng: GameRng,
+    jink: Vector3<f32>,
+    jink_until: Timestep,
+}
+
+impl Bot {
+    /// `rng` is usually `round.rng().fork(player as u64)`, the round forks a fresh one on adding it anyway
+    pub fn new(tactic: Tactic, rng: GameRng) -> Bot {
+        Bot {
+            tactic: tactic,
+            rng: rng,
+            jink: Vector3::new(0.0, 0.0, 0.0),
+            jink_until: 0,
+        }
+    }
+
+    pub fn tactic(&self) -> Tactic {
+        self.tactic
+    }
+
+    fn seek(&mut self, view: &SensorView, target: &Contact) -> Commands {
+        let mut commands = Commands::none();
+        let aim = match lead(view.position, view.velocity, target.position, target.velocity, RAM_SPEED) {
+            Some(solution) => solution.aim,
+            None => target.position - view.position,
+        };
+        if aim.norm() == 0.0 {
+            return commands;
+        }
+        commands.thrust = aim.normalize() * MAX_THRUST;
+        aim_at(view, aim, &mut commands);
+        commands
+    }
+
+    fn kite(&mut self, view: &SensorView, target: &Contact) -> Commands {
+        let mut commands = Commands::none();
+        commands.autopilot = Some(Autopilot::Orbit { center: target.position, radius: KITE_RANGE });
+        aim_at(view, target.position - view.position, &mut commands);
+        commands
+    }
+
+    fn dodge(&mut self, view: &SensorView, target: &Contact) -> Commands {
+        let mut commands = Commands::none();
+        let line = target.position - view.position;
+        if view.time >= self.jink_until {
+            self.jink = self.sideways(line);
+            self.jink_until = view.time + JINK_MS;
+        }
+        let mut thrust = self.jink;
+        if line.norm() > 0.0 && line.norm() < PERSONAL_SPACE {
+            thrust = thrust - line.normalize();
+        }
+        // backing off can cancel out a jink picked for an older line, jink across this one if it does
+        let thrust = if thrust.norm() > 1e-4 { thrust.normalize() } else { self.sideways(line) };
+        commands.thrust = thrust * MAX_THRUST;
+        aim_at(view, line, &mut commands);
+        commands
+    }
+
+    /// A random unit vector at right angles to `line`
+    fn sideways(&mut self, line: Vector3<f32>) -> Vector3<f32> {
+        let line = if line.norm() > 0.0 { line.normalize() } else { Vector3::new(1.0, 0.0, 0.0) };
+        let mut across = line.cross(&Vector3::new(0.0, 0.0, 1.0));
+        if across.norm() < 1e-4 {
+            across = line.cross(&Vector3::new(0.0, 1.0, 0.0));
+        }
+        let across = across.normalize();
+        let up = line.cross(&across);
+        let angle = self.rng.gen::<f32>() * 2.0 * PI;
+        across * angle.cos() + up * angle.sin()
+    }
+}
+
+impl ShipController for Bot {
+    fn tick(&mut self, view: &SensorView, _scanner: &Scanner) -> Result<Commands, Fault> {
+        let target = match nearest(view) {
+            Some(target) => target,
+            None => return Ok(Commands::none()),
+        };
+        let commands = match self.tactic {
+            Tactic::Drifter => Commands::none(),
+            Tactic::Seeker => self.seek(view, target),
+            Tactic::Kiter => self.kite(view, target),
+            Tactic::Dodger => self.dodge(view, target),
+        };
+        Ok(commands)
+    }
+
+    fn reseed(&mut self, rng: GameRng) {
+        self.rng = rng;
+    }
+}
+
+/// The closest contact not on our team, ties go to the lowest player id so the choice never
+/// depends on ordering
+fn nearest(view: &SensorView) -> Option<&Contact> {
+    let distance = |contact: &Contact| (contact.position - view.position).norm();
+    let enemies = view.contacts.iter().filter(|contact| view.team.is_none() || contact.team != view.team);
+    enemies.fold(None, |best: Option<&Contact>, contact| {
+        match best {
+            Some(best) if (distance(best), best.player) <= (distance(contact), contact.player) => {
+                Some(best)
+            },
+            _ => Some(contact),
+        }
+    })
+}
+
+/// Turn toward `aim` and fire once it's dead ahead
+fn aim_at(view: &SensorView, aim: Vector3<f32>, commands: &mut Commands) {
+    commands.torque = steer(view.orientation, view.spin, aim);
+    if aim.norm() > 0.0 && view.heading().dot(&aim.normalize()) >= FIRING_CONE {
+        commands.fire = true;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::rc::Rc;
+    use na::Vector3;
+    use engine::engine::Round;
+    use engine::spatial::Space;
+    use game::board::{Board, Team};
+    use game::ship::Ship;
+    use super::*;
+
+    fn scanner() -> Scanner {
+        Scanner::new(Rc::new(Space::new()), &view(Vector3::new(10.0, 0.0, 0.0)), 100.0)
+    }
+
+    fn view(target: Vector3<f32>) -> SensorView {
+        let mut board = Board::new();
+        board.add_ship(1, Ship::at(Vector3::new(0.0, 0.0, 0.0)));
+        board.add_ship(2, Ship::at(target));
+        SensorView::from_board(&board, 1).unwrap()
+    }
+
+    #[test]
+    fn test_names() {
+        for tactic in &[Tactic::Drifter, Tactic::Seeker, Tactic::Kiter, Tactic::Dodger] {
+            assert_eq!(Some(*tactic), Tactic::from_name(tactic.name()));
+        }
+        assert_eq!(None, Tactic::from_name("rammer"));
+    }
+
+    #[test]
+    fn test_drifter() {
+        let mut bot = Bot::new(Tactic::Drifter, GameRng::new(1));
+        let commands = bot.tick(&view(Vector3::new(10.0, 0.0, 0.0)), &scanner()).unwrap();
+        assert_eq!(Commands::none(), commands);
+    }
+
+    #[test]
+    fn test_seeker() {
+        let mut bot = Bot::new(Tactic::Seeker, GameRng::new(1));
+        let commands = bot.tick(&view(Vector3::new(10.0, 0.0, 0.0)), &scanner()).unwrap();
+        assert!(commands.thrust.x > 0.0, "heads for the target");
+        assert!(commands.fire, "target is dead ahead");
+
+        let commands = bot.tick(&view(Vector3::new(0.0, 10.0, 0.0)), &scanner()).unwrap();
+        assert!(commands.torque.z > 0.0, "turns toward the target");
+        assert!(!commands.fire);
+    }
+
+    #[test]
+    fn test_ignores_teammates() {
+        let mut board = Board::new();
+        board.add_ship(1, Ship::at(Vector3::new(0.0, 0.0, 0.0)));
+        board.add_ship(2, Ship::at(Vector3::new(10.0, 0.0, 0.0)));
+        board.add_ship(3, Ship::at(Vector3::new(0.0, 50.0, 0.0)));
+        for (id, team) in Team::defaults(2) {
+            board.add_team(id, team);
+        }
+        board.join_team(1, Some(1));
+        board.join_team(2, Some(1));
+        board.join_team(3, Some(2));
+        let view = SensorView::from_board(&board, 1).unwrap();
+        assert_eq!(3, nearest(&view).expect("an enemy").player);
+    }
+
+    #[test]
+    fn test_kiter() {
+        let mut bot = Bot::new(Tactic::Kiter, GameRng::new(1));
+        let target = Vector3::new(10.0, 0.0, 0.0);
+        let commands = bot.tick(&view(target), &scanner()).unwrap();
+        assert_eq!(Some(Autopilot::Orbit { center: target, radius: KITE_RANGE }), commands.autopilot);
+    }
+
+    #[test]
+    fn test_dodger_is_deterministic() {
+        let fly = |seed| {
+            let mut bot = Bot::new(Tactic::Dodger, GameRng::new(seed));
+            let mut view = view(Vector3::new(0.0, 80.0, 0.0));
+            (0..5).map(|i| {
+                view.time = i * JINK_MS;
+                bot.tick(&view, &scanner()).unwrap().thrust
+            }).collect::<Vec<_>>()
+        };
+        assert_eq!(fly(7), fly(7));
+        assert!(fly(7) != fly(8));
+        for thrust in fly(7) {
+            assert!(thrust.y.abs() < 1e-3, "jinks across the line of fire, not along it");
+        }
+    }
+
+    #[test]
+    fn test_dodger_on_top_of_target() {
+        let mut bot = Bot::new(Tactic::Dodger, GameRng::new(7));
+        let commands = bot.tick(&view(Vector3::new(0.0, 0.0, 0.0)), &scanner()).unwrap();
+        assert!(commands.thrust.iter().all(|x| x.is_finite()), "{:?}", commands.thrust);
+        assert!((commands.thrust.norm() - MAX_THRUST).abs() < 1e-3, "still jinks");
+
+        bot.jink = Vector3::new(0.0, 1.0, 0.0); // as if it had jinked straight at where the target is now
+        let commands = bot.tick(&view(Vector3::new(0.0, 10.0, 0.0)), &scanner()).unwrap();
+        assert!(commands.thrust.iter().all(|x| x.is_finite()), "{:?}", commands.thrust);
+        assert!((commands.thrust.norm() - MAX_THRUST).abs() < 1e-3, "still jinks");
+        assert!(commands.thrust.y.abs() < 1e-3, "not toward the target: {:?}", commands.thrust);
+    }
+
+    #[test]
+    fn test_dodger_follows_round_seed() {
+        let fly = |seed, reseed| {
+            let mut round = Round::new();
+            round.add_ship(1, Ship::at(Vector3::new(0.0, 0.0, 0.0)));
+            round.add_ship(2, Ship::at(Vector3::new(0.0, 30.0, 0.0)));
+            round.reseed(seed);
+            let rng = round.rng().fork(1);
+            round.add_controller(1, Box::new(Bot::new(Tactic::Dodger, rng)));
+            round.reseed(reseed);
+            round.fast_forward(200);
+            round.board.ships.get(&1).expect("dodger").position()
+        };
+        assert_eq!(fly(5, 5), fly(1, 5), "only the round's seed counts");
+        assert!(fly(5, 5) != fly(6, 6));
+    }
+
+    #[test]
+    fn test_seeker_closes_in() {
+        let mut round = Round::new();
+        round.add_ship(1, Ship::at(Vector3::new(0.0, 0.0, 0.0)));
+        round.add_ship(2, Ship::at(Vector3::new(40.0, 10.0, 0.0)));
+        round.add_controller(1, Box::new(Bot::new(Tactic::Seeker, GameRng::new(1))));
+        round.add_controller(2, Box::new(Bot::new(Tactic::Drifter, GameRng::new(2))));
+        round.fast_forward(150);
+        let seeker = round.board.ships.get(&1).expect("seeker").position();
+        let drifter = round.board.ships.get(&2).expect("drifter").position();
+        assert!((drifter - seeker).norm() < 30.0, "seeker should be closing on the drifter");
+    }
+}
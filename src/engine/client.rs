@@ -1,3 +1,4 @@
+use std::io;
 use std::io::{Read, Write};
 use std::time::Duration;
 use std::mem;
@@ -5,6 +6,7 @@ use std::net::TcpStream;
 use bytes::{ByteOrder, LittleEndian};
 
 use super::frame::Frame;
+use super::protocol::{ClientMessage, ServerMessage};
 
 pub struct Client {
     connection: Option<TcpStream>, // TODO: figure out a better way to mock this
@@ -41,6 +43,18 @@ impl Client {
         results
     }
 
+    /// Reads and decodes any complete messages from the server, dropping ones that don't decode
+    pub fn read_messages(&mut self) -> Vec<ServerMessage> {
+        self.read_frames().iter()
+            .filter_map(|frame| ServerMessage::from_bytes(frame.bytes()))
+            .collect()
+    }
+
+    pub fn send(&mut self, message: &ClientMessage) -> io::Result<()> {
+        let mut connection = self.connection.as_ref().expect("not connected");
+        connection.write_all(&message.to_frame())
+    }
+
     fn process_frame(&mut self, buffer: &[u8], results: &mut Vec<Frame>) {
         let len = buffer.len();
         let frame = mem::replace(&mut self.current_frame, None);
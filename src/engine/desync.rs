@@ -0,0 +1,219 @@
+use std::fmt;
+
+use na::Vector3;
+
+use game::board::{Board, PlayerId, TeamId, Timestep};
+
+/// Replays keep a checkpoint this often, in ticks
+pub const CHECKPOINT_TICKS: u32 = 10;
+
+/// Differences between two boards past this many aren't listed
+const MAX_DIFFS: usize = 20;
+
+/// FNV-1a over the raw bits of the state, cheap and the same on every machine
+/// (unlike std's hasher, which is keyed differently every run)
+pub struct StateHasher {
+    state: u64,
+}
+
+impl StateHasher {
+    pub fn new() -> StateHasher {
+        StateHasher { state: 0xcbf2_9ce4_8422_2325 }
+    }
+
+    pub fn write_u8(&mut self, byte: u8) {
+        self.state ^= byte as u64;
+        self.state = self.state.wrapping_mul(0x0000_0100_0000_01b3);
+    }
+
+    pub fn write_u32(&mut self, x: u32) {
+        for i in 0..4 {
+            self.write_u8((x >> (i * 8)) as u8);
+        }
+    }
+
+    /// Floats are hashed bit for bit, a simulation that's off in the last place has diverged
+    pub fn write_f32(&mut self, x: f32) {
+        self.write_u32(x.to_bits());
+    }
+
+    pub fn write_vector(&mut self, v: Vector3<f32>) {
+        self.write_f32(v.x);
+        self.write_f32(v.y);
+        self.write_f32(v.z);
+    }
+
+    /// The time, every ship in player order and every player's team
+    pub fn write_board(&mut self, board: &Board) {
+        self.write_u32(board.time());
+        for (player, ship) in &board.ships {
+            self.write_u8(*player);
+            self.write_vector(ship.position());
+            for x in ship.orientation().matrix().iter() {
+                self.write_f32(*x);
+            }
+            self.write_vector(ship.velocity());
+            self.write_vector(ship.spin());
+            self.write_f32(ship.hull());
+        }
+        let mut members: Vec<(&PlayerId, &TeamId)> = board.members.iter().collect();
+        members.sort();
+        for (player, team) in members {
+            self.write_u8(*player);
+            self.write_u8(*team);
+        }
+    }
+
+    pub fn finish(&self) -> u64 {
+        self.state
+    }
+}
+
+/// The state hash of a round at some point, for checking a re-run of it against
+/// The board is only there when whoever made the checkpoint could share all of it
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct Checkpoint {
+    pub time: Timestep,
+    pub hash: u64,
+    pub board: Option<Board>,
+}
+
+impl Checkpoint {
+    /// Whether a simulation that got `hash` and `board` at this checkpoint's time has drifted from it
+    pub fn verify(&self, hash: u64, board: &Board) -> Option<Desync> {
+        if hash == self.hash {
+            return None;
+        }
+        Some(Desync {
+            time: self.time,
+            expected: self.hash,
+            actual: hash,
+            diff: self.board.as_ref().map_or(Vec::new(), |expected| diff(expected, board)),
+        })
+    }
+}
+
+/// Where two runs of the same round first stopped agreeing
+#[derive(Clone, PartialEq, Debug)]
+pub struct Desync {
+    pub time: Timestep,
+    pub expected: u64,
+    pub actual: u64,
+    /// How the boards differ, empty if the expected board wasn't known or they're the same
+    /// (when it's the physics or the rest of the round that's drifted)
+    pub diff: Vec<String>,
+}
+
+impl fmt::Display for Desync {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "desync at {}ms, expected {:016x} got {:016x}", self.time, self.expected, self.actual)?;
+        for line in &self.diff {
+            write!(f, "\n  {}", line)?;
+        }
+        Ok(())
+    }
+}
+
+/// Everything that differs between two boards, one line per ship field or team membership
+pub fn diff(expected: &Board, actual: &Board) -> Vec<String> {
+    let mut diffs = Vec::new();
+    if expected.time() != actual.time() {
+        diffs.push(format!("time: {} != {}", expected.time(), actual.time()));
+    }
+    for (player, ship) in &expected.ships {
+        let other = match actual.ships.get(player) {
+            Some(other) => other,
+            None => {
+                diffs.push(format!("ship {}: missing", player));
+                continue;
+            },
+        };
+        let mut field = |name: &str, a: String, b: String| {
+            if a != b {
+                diffs.push(format!("ship {} {}: {} != {}", player, name, a, b));
+            }
+        };
+        field("position", format!("{:?}", ship.position()), format!("{:?}", other.position()));
+        field("orientation", format!("{:?}", ship.orientation()), format!("{:?}", other.orientation()));
+        field("velocity", format!("{:?}", ship.velocity()), format!("{:?}", other.velocity()));
+        field("spin", format!("{:?}", ship.spin()), format!("{:?}", other.spin()));
+        field("hull", format!("{:?}", ship.hull()), format!("{:?}", other.hull()));
+    }
+    for player in actual.ships.keys().filter(|player| !expected.ships.contains_key(player)) {
+        diffs.push(format!("ship {}: unexpected", player));
+    }
+    let mut players: Vec<&PlayerId> = expected.members.keys().chain(actual.members.keys()).collect();
+    players.sort();
+    players.dedup();
+    for player in players {
+        let (a, b) = (expected.members.get(player), actual.members.get(player));
+        if a != b {
+            diffs.push(format!("player {} team: {:?} != {:?}", player, a, b));
+        }
+    }
+    if diffs.len() > MAX_DIFFS {
+        let more = diffs.len() - MAX_DIFFS;
+        diffs.truncate(MAX_DIFFS);
+        diffs.push(format!("... and {} more", more));
+    }
+    diffs
+}
+
+#[cfg(test)]
+mod test {
+    use game::ship::Ship;
+    use super::*;
+
+    fn board() -> Board {
+        let mut board = Board::new();
+        board.add_ship(1, Ship::at(Vector3::new(1.0, 2.0, 3.0)));
+        board.add_ship(2, Ship::at(Vector3::new(-1.0, 0.0, 0.0)));
+        board
+    }
+
+    fn hash(board: &Board) -> u64 {
+        let mut hasher = StateHasher::new();
+        hasher.write_board(board);
+        hasher.finish()
+    }
+
+    #[test]
+    fn test_hash() {
+        assert_eq!(hash(&board()), hash(&board()));
+        let mut moved = board();
+        moved.ships.insert(2, Ship::at(Vector3::new(-1.0, 0.0, 1e-6)));
+        assert!(hash(&board()) != hash(&moved), "the tiniest difference shows up");
+        let mut later = board();
+        later.advance(10);
+        assert!(hash(&board()) != hash(&later));
+    }
+
+    #[test]
+    fn test_diff() {
+        assert!(diff(&board(), &board()).is_empty());
+        let mut actual = board();
+        actual.ships.insert(2, Ship::at(Vector3::new(-1.0, 0.5, 0.0)));
+        actual.remove_ship(1);
+        actual.add_ship(3, Ship::at_origin());
+        let lines = diff(&board(), &actual);
+        assert_eq!(3, lines.len(), "{:?}", lines);
+        assert!(lines[0].starts_with("ship 1: missing"));
+        assert!(lines[1].starts_with("ship 2 position"));
+        assert_eq!("ship 3: unexpected", lines[2]);
+    }
+
+    #[test]
+    fn test_verify() {
+        let checkpoint = Checkpoint { time: 0, hash: hash(&board()), board: Some(board()) };
+        assert_eq!(None, checkpoint.verify(hash(&board()), &board()));
+
+        let mut actual = board();
+        actual.ships.insert(1, Ship::at(Vector3::new(0.0, 0.0, 0.0)));
+        let desync = checkpoint.verify(hash(&actual), &actual).expect("desync");
+        assert_eq!(checkpoint.hash, desync.expected);
+        assert_eq!(vec!["ship 1 position"], desync.diff.iter().map(|l| &l[..15]).collect::<Vec<_>>());
+
+        let blind = Checkpoint { board: None, ..checkpoint };
+        assert!(blind.verify(hash(&actual), &actual).expect("desync").diff.is_empty());
+    }
+}
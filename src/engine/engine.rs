@@ -1,57 +1,554 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
+use std::mem;
+use std::rc::Rc;
 
-use na::Vector3;
-use ncollide::shape::Ball;
-use nphysics3d::world::World;
+use na::{Translation3, Vector3};
+use ncollide::shape::{Ball, Cuboid, Cylinder};
 use nphysics3d::object::RigidBody;
 use time;
 
-use game::board::{Board, PlayerId};
-use game::ship::Ship;
+use engine::desync::{Checkpoint, StateHasher, CHECKPOINT_TICKS};
+use engine::replay::{Frame, Replay};
+use engine::save::{BodyState, SaveError, SavedRound};
+use engine::sim::SimReport;
+use engine::spatial::Space;
+use game::autopilot;
+use game::autopilot::Autopilot;
+use game::board::{Board, PlayerId, TeamId};
+use game::boundary::{self, Boundary};
+use game::controller::{Commands, Fault, FaultRecord, SensorView, ShipController};
+use game::event::Event;
+use game::gravity;
+use game::map::{self, Bounds, Map};
+use game::mode::{FriendlyFire, GameMode, Limits, Results, Scoreboard};
+use game::obstacle::Obstacle;
+use game::rng::GameRng;
+use game::scanner::{RayHit, Scanner};
+use game::sensors::SensorModel;
+use game::ship::{ship_mass, Ship, SHIP_DENSITY, SHIP_RADIUS};
 
 pub struct Round {
     last_tick: f64,
     pub board: Board,
-    world: World<f32>,
-    bodies: HashMap<PlayerId, RigidBody<f32>>,
+    space: Rc<Space>,
+    obstacles: Vec<Obstacle>,
+    /// Where the round is being played, its obstacles are added to the world when it's loaded
+    pub map: Map,
+    /// What happens to ships outside the map's bounds
+    boundary: Boundary,
+    pub sensors: SensorModel,
+    /// Run in player order every tick, so matches play out the same way every time
+    controllers: BTreeMap<PlayerId, Box<ShipController>>,
+    cooldowns: HashMap<PlayerId, u32>,
+    faults: HashMap<PlayerId, FaultRecord>,
+    /// Where each ship on `Autopilot::Hold` is holding
+    anchors: HashMap<PlayerId, Vector3<f32>>,
+    events: Vec<Event>,
+    pub scoreboard: Scoreboard,
+    /// How much damage ships on the same team do to each other
+    pub friendly_fire: FriendlyFire,
+    rng: GameRng,
+    /// Everything that's gone into the round since `record` was called
+    recording: Option<Replay>,
+    /// Ships added since the last tick, for the next recorded frame
+    joined: Vec<(PlayerId, Option<TeamId>, Ship)>,
 }
 
 const TIMESTEP_S: f64 = 0.01; // physics runs at 100 steps per second
-const TICKS_TO_MS: u32 = 10;
+pub const TICKS_TO_MS: u32 = 10;
+
+const BEAM_RANGE: f32 = 100.0;
+const BEAM_DAMAGE: f32 = 10.0;
+const BEAM_COOLDOWN_TICKS: u32 = 50;
+
+const SPAWN_RADIUS: f32 = 20.0;
+const SPAWN_SLOTS: f32 = 8.0;
+
+/// How many budget overruns a controller gets before its ship is disqualified
+const MAX_STRIKES: u32 = 3;
+/// A strike is forgiven after this many clean ticks in a row
+const CLEAN_TICKS_PER_STRIKE: u32 = 100;
 
 impl Round {
     pub fn new() -> Round {
-        let mut world = World::new();
-        world.set_gravity(Vector3::new(0.0, 0.0, 0.0));
-
         Round {
             last_tick: time::precise_time_s(),
             board: Board::new(),
-            world: world,
-            bodies: HashMap::new(),
+            space: Rc::new(Space::new()),
+            obstacles: Vec::new(),
+            map: Map::empty(),
+            boundary: Boundary::Open,
+            sensors: SensorModel::new(),
+            controllers: BTreeMap::new(),
+            cooldowns: HashMap::new(),
+            faults: HashMap::new(),
+            anchors: HashMap::new(),
+            events: Vec::new(),
+            scoreboard: Scoreboard::new(GameMode::Deathmatch, Limits::none()),
+            friendly_fire: FriendlyFire::Full,
+            rng: GameRng::new(0),
+            recording: None,
+            joined: Vec::new(),
+        }
+    }
+
+    pub fn seed(&self) -> u32 {
+        self.rng.seed()
+    }
+
+    /// Start the round's randomness over from `seed`
+    /// Controllers already in the round get new generators forked from it
+    pub fn reseed(&mut self, seed: u32) {
+        self.rng = GameRng::new(seed);
+        self.scoreboard.seed = seed;
+        for (player, controller) in self.controllers.iter_mut() {
+            controller.reseed(self.rng.fork(*player as u64));
         }
     }
 
+    /// Randomness for the simulation, nothing else in a round may use any other source
+    pub fn rng(&mut self) -> &mut GameRng {
+        &mut self.rng
+    }
+
     pub fn add_ship(&mut self, player: PlayerId, ship: Ship) {
-        // TODO: magic numbers
+        if self.recording.is_some() {
+            self.joined.push((player, self.board.team_of(player), ship.clone()));
+        }
         // TODO: figure out the real shape
-        let rad = 0.5;
-        let mut rb: RigidBody<f32> = RigidBody::new_dynamic(Ball::new(rad), 1.0, 0.3, 0.6);
+        let mut rb: RigidBody<f32> = RigidBody::new_dynamic(Ball::new(SHIP_RADIUS), SHIP_DENSITY, 0.3, 0.6);
         rb.append_translation(&ship.translation());
-        self.bodies.insert(player, rb);
+        rb.set_deactivation_threshold(None); // ships coast forever, never let them sleep
+        let handle = self.space.world.borrow_mut().add_rigid_body(rb);
+        self.space.add_body(player, handle);
         self.board.add_ship(player, ship);
+        self.scoreboard.join(player);
+    }
+
+    /// A cheap hash of everything that decides how the round plays out from here: the board,
+    /// the physics bodies, cooldowns, scores and the random number generator
+    /// Two runs of the same round should agree on it after every tick
+    pub fn state_hash(&self) -> u64 {
+        let mut hasher = StateHasher::new();
+        hasher.write_board(&self.board);
+        for (player, handle) in self.space.bodies.borrow().iter() {
+            let rb = handle.borrow();
+            hasher.write_u8(*player);
+            hasher.write_vector(rb.position().translation.vector);
+            hasher.write_vector(rb.lin_vel());
+            hasher.write_vector(rb.ang_vel());
+        }
+        let mut cooldowns: Vec<(&PlayerId, &u32)> = self.cooldowns.iter().collect();
+        cooldowns.sort();
+        for (player, ticks) in cooldowns {
+            hasher.write_u8(*player);
+            hasher.write_u32(*ticks);
+        }
+        for (player, score) in self.scoreboard.standings() {
+            hasher.write_u8(player);
+            hasher.write_u32(score as u32);
+        }
+        for x in &self.rng.state() {
+            hasher.write_u32(*x);
+        }
+        hasher.finish()
+    }
+
+    /// The round's state hash as of now, with the whole board if `with_board`
+    pub fn checkpoint(&self, with_board: bool) -> Checkpoint {
+        Checkpoint {
+            time: self.board.time(),
+            hash: self.state_hash(),
+            board: if with_board { Some(self.board.clone()) } else { None },
+        }
+    }
+
+    /// Everything needed to carry on with the round from where it is now, between two ticks
+    /// Controllers and any recording in progress aren't included
+    pub fn save(&self) -> Vec<u8> {
+        self.snapshot().to_bytes()
+    }
+
+    /// A round picking up exactly where a saved one left off, controllers have to be added again
+    pub fn load(bytes: &[u8]) -> Result<Round, SaveError> {
+        SavedRound::from_bytes(bytes).map(Round::restore)
+    }
+
+    /// The round as it is now, between two ticks, see `save`
+    pub fn snapshot(&self) -> SavedRound {
+        let bodies = self.space.bodies.borrow().iter()
+            .map(|(player, handle)| {
+                let rb = handle.borrow();
+                (*player, BodyState {
+                    position: *rb.position(),
+                    lin_vel: rb.lin_vel(),
+                    ang_vel: rb.ang_vel(),
+                    active: rb.is_active(),
+                    deactivation_threshold: rb.deactivation_threshold(),
+                })
+            })
+            .collect();
+        SavedRound {
+            board: self.board.clone(),
+            bodies: bodies,
+            obstacles: self.obstacles.clone(),
+            map: self.map.clone(),
+            boundary: self.boundary.clone(),
+            sensors: self.sensors.clone(),
+            cooldowns: self.cooldowns.clone(),
+            faults: self.faults.clone(),
+            anchors: self.anchors.clone(),
+            events: self.events.clone(),
+            scoreboard: self.scoreboard.clone(),
+            friendly_fire: self.friendly_fire,
+            rng: self.rng.clone(),
+        }
+    }
+
+    /// A round put back the way `saved` has it, controllers have to be added again
+    /// Bodies go back into the world scenery first, then ships in player order
+    pub fn restore(saved: SavedRound) -> Round {
+        let mut round = Round::new();
+        for obstacle in saved.obstacles {
+            round.add_obstacle(obstacle);
+        }
+        round.map = saved.map;
+        round.set_boundary(saved.boundary);
+        for (player, ship) in &saved.board.ships {
+            round.add_ship(*player, ship.clone());
+        }
+        // the collision world only takes in new bodies when it steps, so give it a step to
+        // catch up on and then put every body back exactly the way it was
+        round.space.step(TIMESTEP_S as f32);
+        for &(player, ref body) in &saved.bodies {
+            if let Some(rb) = round.space.bodies.borrow().get(&player) {
+                let mut rb = rb.borrow_mut();
+                rb.set_transformation(body.position);
+                rb.set_lin_vel(body.lin_vel);
+                rb.set_ang_vel(body.ang_vel);
+                rb.set_deactivation_threshold(body.deactivation_threshold);
+                if !body.active {
+                    rb.deactivate();
+                }
+            }
+        }
+        round.board = saved.board;
+        round.sensors = saved.sensors;
+        round.cooldowns = saved.cooldowns;
+        round.faults = saved.faults;
+        round.anchors = saved.anchors;
+        round.events = saved.events;
+        round.scoreboard = saved.scoreboard;
+        round.friendly_fire = saved.friendly_fire;
+        round.rng = saved.rng;
+        round
+    }
+
+    /// Start recording everything that goes into the round from here on
+    pub fn record(&mut self) {
+        self.sync_board(); // pick up any burns since the last step
+        self.joined.clear();
+        let mut start = self.snapshot();
+        start.events.clear(); // they happened before the recording
+        self.recording = Some(Replay::new(start));
+    }
+
+    pub fn recording(&self) -> Option<&Replay> {
+        self.recording.as_ref()
+    }
+
+    /// Stop recording and take everything recorded so far
+    pub fn take_recording(&mut self) -> Option<Replay> {
+        self.recording.take()
+    }
+
+    /// Play one recorded tick, with the recorded commands standing in for the controllers
+    pub fn play_frame(&mut self, frame: &Frame) {
+        if self.finished() {
+            return;
+        }
+        for &(player, team, ref ship) in &frame.joined {
+            if team.is_some() {
+                self.board.join_team(player, team);
+            }
+            self.add_ship(player, ship.clone());
+        }
+        self.run_tick(frame.commands.clone());
+    }
+
+    /// Start keeping score under `mode`, everyone already in the round starts from zero
+    pub fn set_mode(&mut self, mode: GameMode, limits: Limits) {
+        self.scoreboard = Scoreboard::new(mode, limits);
+        self.scoreboard.seed = self.rng.seed();
+        for player in self.board.ships.keys() {
+            self.scoreboard.join(*player);
+        }
+    }
+
+    /// How the match turned out, once it's over
+    pub fn results(&self) -> Option<&Results> {
+        self.scoreboard.results()
+    }
+
+    pub fn finished(&self) -> bool {
+        self.scoreboard.results().is_some()
+    }
+
+    /// Add a piece of static scenery, it shows up in collisions and ray casts after the next step
+    pub fn add_obstacle(&mut self, obstacle: Obstacle) {
+        let mut rb: RigidBody<f32> = match obstacle {
+            Obstacle::Asteroid { radius, .. } => RigidBody::new_static(Ball::new(radius), 0.3, 0.6),
+            Obstacle::Block { half_extents, .. } => RigidBody::new_static(Cuboid::new(half_extents), 0.3, 0.6),
+            Obstacle::Station { radius, half_height, .. } => {
+                RigidBody::new_static(Cylinder::new(half_height, radius), 0.3, 0.6)
+            },
+        };
+        let position = obstacle.position();
+        rb.append_translation(&Translation3::new(position.x, position.y, position.z));
+        self.space.world.borrow_mut().add_rigid_body(rb);
+        self.obstacles.push(obstacle);
+        self.space.forget_sight_lines();
+    }
+
+    /// Play the round on `map`, adding its scenery to the world
+    pub fn load_map(&mut self, map: Map) {
+        for obstacle in &map.obstacles {
+            self.add_obstacle(obstacle.clone());
+        }
+        self.map = map;
+        self.update_wrap();
+    }
+
+    pub fn boundary(&self) -> &Boundary {
+        &self.boundary
+    }
+
+    /// Change what happens to ships that leave the map's bounds, maps without bounds are always open
+    pub fn set_boundary(&mut self, boundary: Boundary) {
+        self.boundary = boundary;
+        self.update_wrap();
+    }
+
+    /// Let sensors and spatial queries know whether distances wrap around the arena
+    fn update_wrap(&mut self) {
+        let wrap = self.boundary.wrap_extents(self.map.bounds.as_ref());
+        self.sensors.wrap = wrap;
+        *self.space.wrap.borrow_mut() = wrap;
+        self.space.forget_sight_lines();
+    }
+
+    /// Players whose ships are inside the map's zone called `name`
+    pub fn ships_in_zone(&self, name: &str) -> Vec<PlayerId> {
+        match self.map.zone(name) {
+            Some(zone) => map::players_in(zone, self.board.ships.iter().map(|(p, ship)| (*p, ship.position()))),
+            None => Vec::new(),
+        }
     }
 
     pub fn fire_engine(&mut self, player: PlayerId, vector: Vector3<f32>) {
         // TODO: this function should take into account which way the ship is pointing
-        self.bodies.get_mut(&player)
-            .map(|rb| { rb.apply_central_impulse(vector) })
+        self.space.bodies.borrow().get(&player)
+            .map(|rb| { rb.borrow_mut().apply_central_impulse(vector) })
             .or_else(|| {
                 println!("No rigid body registered for {}", player);
                 None
             });
     }
 
+    /// Add a fresh ship for `player` at their spawn point, unless they already have one
+    /// Maps with spawn points spread each team over its own, otherwise ships start in a ring
+    pub fn spawn_ship(&mut self, player: PlayerId) {
+        if self.board.ships.contains_key(&player) {
+            return;
+        }
+        let team = self.board.team_of(player);
+        let index = match team {
+            Some(_) => self.board.members.iter().filter(|&(p, t)| Some(*t) == team && *p < player).count(),
+            None => (player as usize).saturating_sub(1),
+        };
+        let position = self.map.spawn_point(team, index).unwrap_or_else(|| {
+            let angle = player as f32 / SPAWN_SLOTS * 2.0 * ::std::f32::consts::PI;
+            let lap = (player as usize).saturating_sub(1) / SPAWN_SLOTS as usize;
+            map::stacked(Vector3::new(SPAWN_RADIUS * angle.cos(), SPAWN_RADIUS * angle.sin(), 0.0), lap)
+        });
+        self.add_ship(player, Ship::at(position));
+    }
+
+    /// Hand control of `player`'s ship over to `controller`, replacing any previous one
+    /// The controller's randomness is forked from the round's, see `ShipController::reseed`
+    pub fn add_controller(&mut self, player: PlayerId, mut controller: Box<ShipController>) {
+        controller.reseed(self.rng.fork(player as u64));
+        self.controllers.insert(player, controller);
+    }
+
+    pub fn has_controller(&self, player: PlayerId) -> bool {
+        self.controllers.contains_key(&player)
+    }
+
+    pub fn controller_count(&self) -> usize {
+        self.controllers.len()
+    }
+
+    /// Take every controller out of the round, e.g. to hand them over to the next one
+    pub fn take_controllers(&mut self) -> BTreeMap<PlayerId, Box<ShipController>> {
+        mem::replace(&mut self.controllers, BTreeMap::new())
+    }
+
+    /// Stop controlling `player`'s ship, it keeps drifting on its last course
+    pub fn remove_controller(&mut self, player: PlayerId) {
+        self.controllers.remove(&player);
+    }
+
+    /// Apply one tick's worth of commands to a ship
+    pub fn apply_commands(&mut self, player: PlayerId, commands: &Commands) {
+        let commands = commands.limited();
+        let thrust = match commands.autopilot {
+            Some(ref order) => self.autopilot_thrust(player, order),
+            None => {
+                self.anchors.remove(&player);
+                commands.thrust
+            },
+        };
+        let dt = TIMESTEP_S as f32;
+        if let Some(rb) = self.space.bodies.borrow().get(&player) {
+            let mut rb = rb.borrow_mut();
+            rb.apply_central_impulse(thrust * dt);
+            rb.apply_angular_momentum(commands.torque * dt);
+        }
+        if commands.fire {
+            self.fire_weapon(player);
+        }
+    }
+
+    /// Thrust the autopilot wants for `player` this tick
+    fn autopilot_thrust(&mut self, player: PlayerId, order: &Autopilot) -> Vector3<f32> {
+        let ship = match self.board.ships.get(&player) {
+            Some(ship) => ship.clone(),
+            None => return Vector3::new(0.0, 0.0, 0.0),
+        };
+        if *order != Autopilot::Hold {
+            self.anchors.remove(&player);
+        }
+        match *order {
+            Autopilot::GoTo(point) => autopilot::go_to(&ship, point),
+            Autopilot::MatchVelocity(target) => {
+                // keep flying straight if the target is gone
+                let velocity = self.board.ships.get(&target).map_or(ship.velocity(), |t| t.velocity());
+                autopilot::match_velocity(&ship, velocity)
+            },
+            Autopilot::Hold => {
+                let anchor = *self.anchors.entry(player).or_insert(ship.position());
+                autopilot::go_to(&ship, anchor)
+            },
+            Autopilot::Orbit { center, radius } => autopilot::orbit(&ship, center, radius),
+        }
+    }
+
+    /// Fire `player`'s beam along their heading, damaging the first ship it touches
+    /// Obstacles in the way soak up the shot
+    pub fn fire_weapon(&mut self, player: PlayerId) {
+        if self.cooldowns.get(&player).map_or(false, |&c| c > 0) {
+            return;
+        }
+        let (origin, heading) = match self.board.ships.get(&player) {
+            Some(ship) => (ship.position(), ship.heading()),
+            None => return,
+        };
+        self.cooldowns.insert(player, BEAM_COOLDOWN_TICKS);
+
+        let hit = self.space.raycast(origin, heading, BEAM_RANGE, |hit| hit != RayHit::Ship(player));
+        if let Some((RayHit::Ship(target), _)) = hit {
+            self.damage_ship(target, BEAM_DAMAGE, Some(player));
+        }
+    }
+
+    /// Ships within `radius` of `center`, nearest first
+    pub fn ships_within(&self, center: Vector3<f32>, radius: f32) -> Vec<(PlayerId, f32)> {
+        self.space.ships_within(center, radius)
+    }
+
+    /// The closest ship to `center` within `radius` that `accept` is interested in
+    pub fn nearest_ship<F>(&self, center: Vector3<f32>, radius: f32, accept: F) -> Option<(PlayerId, f32)>
+        where F: Fn(PlayerId) -> bool
+    {
+        self.space.nearest_ship(center, radius, accept)
+    }
+
+    /// The closest ship or obstacle along a ray that `accept` is interested in
+    pub fn raycast<F>(&self, origin: Vector3<f32>, dir: Vector3<f32>, max_dist: f32, accept: F)
+                      -> Option<(RayHit, f32)>
+        where F: Fn(RayHit) -> bool
+    {
+        self.space.raycast(origin, dir, max_dist, accept)
+    }
+
+    /// Whether any obstacle sits between two ships, checked at most once per pair per tick
+    pub fn line_of_sight(&self, a: PlayerId, b: PlayerId) -> bool {
+        self.space.line_of_sight(a, b)
+    }
+
+    /// Spatial queries limited to what a ship's sensors found, as `view`
+    pub fn scanner(&self, view: &SensorView) -> Scanner {
+        Scanner::new(self.space.clone(), view, self.sensors.range)
+    }
+
+    fn damage_ship(&mut self, player: PlayerId, damage: f32, by: Option<PlayerId>) {
+        let damage = match by {
+            Some(shooter) if self.board.teammates(shooter, player) => self.friendly_fire.scale(damage),
+            _ => damage,
+        };
+        if damage <= 0.0 {
+            return;
+        }
+        let destroyed = match self.board.ships.get_mut(&player) {
+            Some(ship) => ship.damage(damage),
+            None => return,
+        };
+        if let Some(shooter) = by {
+            self.emit(Event::Hit { shooter: shooter, target: player, damage: damage });
+        }
+        if destroyed {
+            self.remove_ship(player);
+            self.emit(Event::Destroyed { player: player, by: by });
+        }
+    }
+
+    pub fn remove_ship(&mut self, player: PlayerId) {
+        let body = self.space.remove_body(player);
+        if let Some(handle) = body {
+            self.space.world.borrow_mut().remove_rigid_body(&handle);
+        }
+        self.board.remove_ship(player);
+        self.cooldowns.remove(&player);
+        self.anchors.remove(&player);
+    }
+
+    /// Remove a ship and its controller from the round for good
+    pub fn disqualify(&mut self, player: PlayerId) {
+        self.remove_ship(player);
+        self.controllers.remove(&player);
+        self.faults.remove(&player);
+        self.emit(Event::Disqualified { player: player });
+    }
+
+    /// The board as `player`'s sensors see it
+    /// Sensor noise comes from its own stream for each ship and tick, so looking doesn't
+    /// change what happens next and the same view comes back however often it's asked for
+    pub fn sensor_view(&self, player: PlayerId) -> Option<SensorView> {
+        let mut rng = self.rng.fork((self.board.time() as u64) << 8 | player as u64);
+        self.sensors.view(&self.board, player, &mut rng, |other| self.line_of_sight(player, other))
+    }
+
+    fn emit(&mut self, event: Event) {
+        self.scoreboard.record(&event, &self.board);
+        self.events.push(event);
+    }
+
+    /// Take all of the events that have happened since the last call
+    pub fn drain_events(&mut self) -> Vec<Event> {
+        mem::replace(&mut self.events, Vec::new())
+    }
+
     /// Advance the physics world by as much time as has elapsed since the last tick
     /// Always steps the world ahead at 100fps, may make multiple steps per call
     pub fn tick(&mut self) -> u32 {
@@ -60,12 +557,217 @@ impl Round {
         ticks
     }
 
+    /// Run the simulation ahead by `ticks` steps as fast as possible, ignoring the wall clock
+    pub fn fast_forward(&mut self, ticks: u32) -> SimReport {
+        let started = time::precise_time_s();
+        self.step(ticks);
+        let elapsed = time::precise_time_s() - started;
+        self.last_tick = time::precise_time_s();
+        SimReport {
+            ticks: ticks,
+            ships: self.space.bodies.borrow().len(),
+            elapsed_s: elapsed,
+        }
+    }
+
     fn tick_ahead(&mut self, ticks: u32) {
+        self.step(ticks);
+        self.last_tick += ticks as f64 * TIMESTEP_S;
+    }
+
+    /// Once the match is over the world stands still
+    fn step(&mut self, ticks: u32) {
         for _ in 0..ticks  {
-            self.world.step(TIMESTEP_S as f32);
+            if self.finished() {
+                return;
+            }
+            let results = self.poll_controllers();
+            self.run_tick(results);
+        }
+    }
+
+    /// Apply one tick's worth of controller output and step everything forward
+    /// Faulted controllers skip their turn, repeat budget offenders are disqualified
+    fn run_tick(&mut self, results: Vec<(PlayerId, Result<Commands, Fault>)>) {
+        if let Some(ref mut replay) = self.recording {
+            let joined = mem::replace(&mut self.joined, Vec::new());
+            replay.frames.push(Frame { joined: joined, commands: results.clone(), check: None });
+        }
+        for (player, result) in results {
+            match result {
+                Ok(commands) => {
+                    self.record_clean(player);
+                    self.apply_commands(player, &commands);
+                },
+                Err(fault) => self.record_fault(player, fault),
+            }
+        }
+        self.apply_gravity();
+        self.space.step(TIMESTEP_S as f32);
+        self.enforce_boundary();
+        self.sync_board();
+        self.crash_into_wells();
+        for cooldown in self.cooldowns.values_mut() {
+            *cooldown = cooldown.saturating_sub(1);
+        }
+        self.board.advance(TICKS_TO_MS);
+        self.scoreboard.check(&self.board);
+        if self.recording.is_some() && self.board.time() % (CHECKPOINT_TICKS * TICKS_TO_MS) == 0 {
+            let checkpoint = self.checkpoint(true);
+            if let Some(frame) = self.recording.as_mut().and_then(|replay| replay.frames.last_mut()) {
+                frame.check = Some(checkpoint);
+            }
+        }
+    }
+
+    /// Ask every controller with a ship still on the board for its commands
+    fn poll_controllers(&mut self) -> Vec<(PlayerId, Result<Commands, Fault>)> {
+        let views: Vec<(PlayerId, SensorView)> = self.controllers.keys()
+            .filter_map(|player| self.sensor_view(*player).map(|view| (*player, view)))
+            .collect();
+        views.into_iter()
+            .filter_map(|(player, view)| {
+                let scanner = self.scanner(&view);
+                self.controllers.get_mut(&player)
+                    .map(|controller| (player, controller.tick(&view, &scanner)))
+            })
+            .collect()
+    }
+
+    /// Faults are reported when they start or change, not every tick they carry on for
+    fn record_fault(&mut self, player: PlayerId, fault: Fault) {
+        let (repeat, strikes) = {
+            let record = self.faults.entry(player).or_insert_with(FaultRecord::default);
+            let repeat = record.last.as_ref() == Some(&fault);
+            if let Fault::OverBudget(_) = fault {
+                record.strikes += 1;
+                record.clean_ticks = 0;
+            }
+            record.last = Some(fault.clone());
+            (repeat, record.strikes)
+        };
+        if !repeat {
+            self.emit(Event::Faulted { player: player, fault: fault });
+        }
+        if strikes >= MAX_STRIKES {
+            self.disqualify(player);
+        }
+    }
+
+    /// A tick without a fault, enough of them in a row and a strike is forgiven
+    fn record_clean(&mut self, player: PlayerId) {
+        let forgiven = match self.faults.get_mut(&player) {
+            Some(record) => {
+                record.last = None;
+                record.clean_ticks += 1;
+                if record.clean_ticks >= CLEAN_TICKS_PER_STRIKE {
+                    record.clean_ticks = 0;
+                    record.strikes = record.strikes.saturating_sub(1);
+                }
+                record.strikes == 0
+            },
+            None => false,
+        };
+        if forgiven {
+            self.faults.remove(&player);
+        }
+    }
+
+    /// Pull every ship toward the map's gravity wells for the next step
+    fn apply_gravity(&mut self) {
+        if self.map.wells.is_empty() {
+            return;
+        }
+        let dt = TIMESTEP_S as f32;
+        for handle in self.space.bodies.borrow().values() {
+            let mut rb = handle.borrow_mut();
+            let position = rb.position().translation.vector;
+            let pull = gravity::acceleration(&self.map.wells, position);
+            rb.apply_central_impulse(pull * (ship_mass() * dt));
+        }
+    }
+
+    /// Destroy any ships that have hit a planet
+    fn crash_into_wells(&mut self) {
+        let crashed: Vec<PlayerId> = self.board.ships.iter()
+            .filter(|&(_, ship)| gravity::crashed(&self.map.wells, ship.position()))
+            .map(|(player, _)| *player)
+            .collect();
+        for player in crashed {
+            self.remove_ship(player);
+            self.emit(Event::Destroyed { player: player, by: None });
+        }
+    }
+
+    /// Where `player`'s ship will coast over the next `steps` physics steps, bent by gravity
+    pub fn predict_trajectory(&self, player: PlayerId, steps: usize) -> Vec<Vector3<f32>> {
+        match self.board.ships.get(&player) {
+            Some(ship) => {
+                gravity::predict(&self.map.wells, ship.position(), ship.velocity(), TIMESTEP_S as f32, steps)
+            },
+            None => Vec::new(),
+        }
+    }
+
+    /// Deal with any ships that have strayed outside the map's bounds
+    fn enforce_boundary(&mut self) {
+        let bounds = match self.map.bounds {
+            Some(ref bounds) => bounds.clone(),
+            None => return,
+        };
+        if self.boundary == Boundary::Open {
+            return;
+        }
+        let dt = TIMESTEP_S as f32;
+        let mut burned = Vec::new();
+        for (player, handle) in self.space.bodies.borrow().iter() {
+            let mut rb = handle.borrow_mut();
+            let position = rb.position().translation.vector;
+            match self.boundary {
+                Boundary::Open => (),
+                Boundary::Walls | Boundary::Wrap if self.boundary.wrap_extents(Some(&bounds)).is_none() => {
+                    if let Some((inside, velocity)) = boundary::bounce(&bounds, position, rb.lin_vel()) {
+                        let back = inside - position;
+                        rb.append_translation(&Translation3::new(back.x, back.y, back.z));
+                        rb.set_lin_vel(velocity);
+                    }
+                },
+                Boundary::PushBack { stiffness } => {
+                    rb.apply_central_impulse(boundary::push_back(&bounds, position, stiffness) * dt);
+                },
+                Boundary::Damage { per_second, shrink_per_second, min_scale } => {
+                    let elapsed_s = self.board.time() as f32 / 1000.0;
+                    if boundary::zone(&bounds, elapsed_s, shrink_per_second, min_scale).depth(position) > 0.0 {
+                        burned.push((*player, per_second * dt));
+                    }
+                },
+                Boundary::Wrap => {
+                    if let Bounds::Box { half_extents } = bounds {
+                        let moved = boundary::wrap(position, half_extents) - position;
+                        if moved.norm() > 0.0 {
+                            rb.append_translation(&Translation3::new(moved.x, moved.y, moved.z));
+                        }
+                    }
+                },
+            }
+        }
+        for (player, damage) in burned {
+            self.damage_ship(player, damage, None);
+        }
+    }
+
+    /// Copy the state of the physics bodies back onto the ships on the board
+    fn sync_board(&mut self) {
+        for (player, handle) in self.space.bodies.borrow().iter() {
+            let rb = handle.borrow();
+            let position = rb.position();
+            self.board.ships.get_mut(player).map(|ship| {
+                ship.update(position.translation.vector,
+                            position.rotation.to_rotation_matrix(),
+                            rb.lin_vel(),
+                            rb.ang_vel())
+            });
         }
-        self.last_tick += ticks as f64 * TIMESTEP_S;
-        self.board.advance(ticks * TICKS_TO_MS);
     }
 
     fn dt_s(&self) -> f64 {
@@ -79,6 +781,10 @@ mod test {
     use std::thread;
     use std::time::Duration;
     use nphysics3d::math::Point;
+    use engine::bots::{Bot, Tactic};
+    use game::board::Team;
+    use game::gravity::Well;
+    use game::ship::{ship_mass, MAX_HULL};
     use super::*;
 
     #[test]
@@ -86,10 +792,141 @@ mod test {
         let mut round = Round::new();
         let ship = Ship::at_origin();
         round.add_ship(2, ship);
-        assert_eq!(1, round.bodies.len());
+        assert_eq!(1, round.space.bodies.borrow().len());
         assert_eq!(1, round.board.ships.len());
     }
 
+    #[test]
+    fn test_spawn_ship() {
+        let mut round = Round::new();
+        round.spawn_ship(1);
+        round.spawn_ship(2);
+        let first = round.board.ships.get(&1).expect("ship 1").position();
+        let second = round.board.ships.get(&2).expect("ship 2").position();
+        assert!((first - second).norm() > 1.0, "ships spawn apart");
+
+        round.spawn_ship(1);
+        assert_eq!(2, round.space.bodies.borrow().len(), "existing ships aren't replaced");
+
+        for player in 3..20 {
+            round.spawn_ship(player);
+        }
+        let positions: Vec<Vector3<f32>> = round.board.ships.values().map(|ship| ship.position()).collect();
+        for (i, a) in positions.iter().enumerate() {
+            for b in &positions[i + 1..] {
+                assert!((*a - *b).norm() > 1.0, "more ships than spawn slots still spawn apart");
+            }
+        }
+
+        round.spawn_ship(0);
+        assert!(round.board.ships.contains_key(&0), "player 0 gets a ship too");
+    }
+
+    #[test]
+    fn test_load_map() {
+        let mut round = Round::new();
+        round.load_map(Map::from_toml(include_str!("../../maps/belt.toml")).unwrap());
+        for (id, team) in Team::defaults(2) {
+            round.board.add_team(id, team);
+        }
+        for player in 1..4 {
+            round.board.join_team(player, None);
+            round.spawn_ship(player);
+        }
+        let position = |player| round.board.ships.get(&player).expect("ship").position();
+        assert_eq!(Vector3::new(-150.0, -20.0, 0.0), position(1));
+        assert_eq!(Vector3::new(150.0, -20.0, 0.0), position(2));
+        assert_eq!(Vector3::new(-150.0, 20.0, 0.0), position(3), "second spawn on team 1");
+        assert!(round.ships_in_zone("center").is_empty());
+
+        round.fast_forward(1);
+        let from = Vector3::new(-150.0, 0.0, 0.0);
+        let hit = round.raycast(from, Vector3::new(1.0, 0.0, 0.0), 200.0, |hit| hit == RayHit::Obstacle);
+        assert_eq!(Some(84.0), hit.map(|(_, dist)| dist.round()), "hits the asteroid in the way");
+    }
+
+    fn star() -> Map {
+        let mut map = Map::empty();
+        map.wells.push(Well { position: Vector3::new(0.0, 0.0, 0.0), strength: 5000.0, radius: 5.0 });
+        map
+    }
+
+    #[test]
+    fn test_gravity() {
+        let mut round = Round::new();
+        round.load_map(star());
+        round.add_ship(1, Ship::at(Vector3::new(50.0, 0.0, 0.0)));
+        round.fast_forward(10);
+        let ship = round.board.ships.get(&1).expect("ship 1").clone();
+        assert!(ship.velocity().x < 0.0, "falling toward the star");
+        assert_eq!(0.0, ship.velocity().y);
+
+        let predicted = round.predict_trajectory(1, 50);
+        round.fast_forward(50);
+        let actual = round.board.ships.get(&1).expect("ship 1").position();
+        assert!((predicted[49] - actual).norm() < 0.05, "predicted {:?}, got {:?}", predicted[49], actual);
+    }
+
+    #[test]
+    fn test_crash_into_well() {
+        let mut round = Round::new();
+        round.load_map(star());
+        round.add_ship(1, Ship::at(Vector3::new(20.0, 0.0, 0.0)));
+        round.fast_forward(500);
+        assert!(round.board.ships.get(&1).is_none());
+        assert!(round.drain_events().contains(&Event::Destroyed { player: 1, by: None }));
+    }
+
+    fn bounded(boundary: Boundary, at: Vector3<f32>, speed: f32) -> Round {
+        let mut round = Round::new();
+        let mut map = Map::empty();
+        map.bounds = Some(Bounds::Box { half_extents: Vector3::new(20.0, 20.0, 20.0) });
+        round.load_map(map);
+        round.set_boundary(boundary);
+        round.add_ship(1, Ship::at(at));
+        round.fire_engine(1, Vector3::new(speed * ship_mass(), 0.0, 0.0));
+        round
+    }
+
+    #[test]
+    fn test_walls() {
+        let mut round = bounded(Boundary::Walls, Vector3::new(15.0, 0.0, 0.0), 10.0);
+        round.fast_forward(100);
+        let ship = round.board.ships.get(&1).expect("ship 1");
+        assert!(ship.position().x <= 20.0, "never gets through the wall");
+        assert!(ship.velocity().x < 0.0, "bounced back");
+    }
+
+    #[test]
+    fn test_push_back() {
+        let mut round = bounded(Boundary::from_name("push").unwrap(), Vector3::new(30.0, 0.0, 0.0), 0.0);
+        round.fast_forward(10);
+        assert!(round.board.ships.get(&1).expect("ship 1").velocity().x < 0.0, "pulled back in");
+    }
+
+    #[test]
+    fn test_damage_zone() {
+        let boundary = Boundary::Damage { per_second: 5.0, shrink_per_second: 0.0, min_scale: 1.0 };
+        let mut round = bounded(boundary, Vector3::new(30.0, 0.0, 0.0), 0.0);
+        round.add_ship(2, Ship::at_origin());
+        round.fast_forward(100);
+        assert!((round.board.ships.get(&1).expect("ship 1").hull() - (MAX_HULL - 5.0)).abs() < 0.01);
+        assert_eq!(MAX_HULL, round.board.ships.get(&2).expect("ship 2").hull(), "safe inside the zone");
+    }
+
+    #[test]
+    fn test_wrap() {
+        let mut round = bounded(Boundary::Wrap, Vector3::new(15.0, 0.0, 0.0), 10.0);
+        round.fast_forward(100);
+        let position = round.board.ships.get(&1).expect("ship 1").position();
+        assert!(position.x < -10.0, "came back in the other side: {:?}", position);
+
+        round.add_ship(2, Ship::at(Vector3::new(18.0, 0.0, 0.0)));
+        round.fast_forward(1);
+        let nearest = round.nearest_ship(position, 10.0, |p| p != 1);
+        assert_eq!(Some(2), nearest.map(|(player, _)| player), "near across the edge");
+    }
+
     #[test]
     fn test_dt() {
         let round = Round::new();
@@ -110,28 +947,340 @@ mod test {
         assert!(round.last_tick >= ticks as f64 * TIMESTEP_S + last_ticked);
     }
 
+    #[test]
+    fn test_fast_forward() {
+        let mut round = Round::new();
+        round.add_ship(1, Ship::at_origin());
+        let report = round.fast_forward(500);
+        assert_eq!(500, report.ticks);
+        assert_eq!(1, report.ships);
+        assert_eq!(5000, round.board.time());
+        assert!(report.elapsed_s < 5.0, "500 ticks should run faster than real time");
+    }
+
+    struct Thruster;
+
+    impl ShipController for Thruster {
+        fn tick(&mut self, _view: &SensorView, _scanner: &Scanner) -> Result<Commands, Fault> {
+            let mut commands = Commands::none();
+            commands.thrust = Vector3::new(1.0, 0.0, 0.0);
+            Ok(commands)
+        }
+    }
+
+    struct OnAutopilot(Autopilot);
+
+    impl ShipController for OnAutopilot {
+        fn tick(&mut self, _view: &SensorView, _scanner: &Scanner) -> Result<Commands, Fault> {
+            let mut commands = Commands::none();
+            commands.autopilot = Some(self.0.clone());
+            Ok(commands)
+        }
+    }
+
+    struct Hog;
+
+    impl ShipController for Hog {
+        fn tick(&mut self, _view: &SensorView, _scanner: &Scanner) -> Result<Commands, Fault> {
+            Err(Fault::OverBudget("too slow".to_string()))
+        }
+    }
+
+    /// Runs over budget on the ticks it's told to and is fine the rest of the time
+    struct Flaky {
+        tick: u32,
+        slow: Vec<u32>,
+    }
+
+    impl ShipController for Flaky {
+        fn tick(&mut self, _view: &SensorView, _scanner: &Scanner) -> Result<Commands, Fault> {
+            self.tick += 1;
+            if self.slow.contains(&self.tick) {
+                Err(Fault::OverBudget("too slow".to_string()))
+            } else {
+                Ok(Commands::none())
+            }
+        }
+    }
+
+    #[test]
+    fn test_controller() {
+        let mut round = Round::new();
+        round.add_ship(1, Ship::at_origin());
+        round.add_controller(1, Box::new(Thruster));
+        round.fast_forward(100);
+        let ship = round.board.ships.get(&1).expect("ship on board");
+        assert!(ship.velocity().x > 0.0, "controller thrust moved the ship");
+    }
+
+    #[test]
+    fn test_autopilot_go_to() {
+        let mut round = Round::new();
+        round.add_ship(1, Ship::at(Vector3::new(0.0, 0.0, 0.0)));
+        let destination = Vector3::new(30.0, 0.0, 0.0);
+        round.add_controller(1, Box::new(OnAutopilot(Autopilot::GoTo(destination))));
+        round.fast_forward(1000);
+        let ship = round.board.ships.get(&1).expect("ship on board");
+        assert!((ship.position() - destination).norm() < 0.5, "arrived at {:?}", ship.position());
+        assert!(ship.velocity().norm() < 0.5, "and stopped");
+    }
+
+    #[test]
+    fn test_autopilot_hold() {
+        let mut round = Round::new();
+        round.add_ship(1, Ship::at(Vector3::new(0.0, 0.0, 0.0)));
+        round.fire_engine(1, Vector3::new(0.0, 1.0, 0.0));
+        round.add_controller(1, Box::new(OnAutopilot(Autopilot::Hold)));
+        round.fast_forward(1000);
+        let ship = round.board.ships.get(&1).expect("ship on board");
+        assert!(ship.position().norm() < 0.5, "came back to where the hold started");
+        assert!(ship.velocity().norm() < 0.5);
+    }
+
+    #[test]
+    fn test_disqualify_over_budget() {
+        let mut round = Round::new();
+        round.add_ship(1, Ship::at_origin());
+        round.add_controller(1, Box::new(Hog));
+
+        round.fast_forward(MAX_STRIKES - 1);
+        assert!(round.board.ships.get(&1).is_some(), "ship skips turns before disqualification");
+        round.fast_forward(1);
+        assert!(round.board.ships.get(&1).is_none());
+        assert!(round.controllers.is_empty());
+
+        let events = round.drain_events();
+        assert_eq!(vec![Event::Faulted { player: 1, fault: Fault::OverBudget("too slow".to_string()) },
+                        Event::Disqualified { player: 1 }],
+                   events, "the same fault over and over is only reported once");
+    }
+
+    #[test]
+    fn test_strikes_decay() {
+        let mut round = Round::new();
+        round.add_ship(1, Ship::at_origin());
+        let later = CLEAN_TICKS_PER_STRIKE * MAX_STRIKES;
+        let slow = (1..MAX_STRIKES).chain(later..later + MAX_STRIKES - 1).collect();
+        round.add_controller(1, Box::new(Flaky { tick: 0, slow: slow }));
+
+        round.fast_forward(later + MAX_STRIKES);
+        assert!(round.board.ships.get(&1).is_some(), "strikes were forgiven in between");
+        let faults = round.drain_events().into_iter()
+            .filter(|event| if let Event::Faulted { .. } = *event { true } else { false })
+            .count();
+        assert_eq!(2, faults, "once for each run of overruns");
+        assert!(round.faults.get(&1).is_some());
+        round.fast_forward(CLEAN_TICKS_PER_STRIKE * MAX_STRIKES);
+        assert!(round.faults.get(&1).is_none(), "and forgotten after long enough");
+    }
+
+    #[test]
+    fn test_fire_weapon() {
+        let mut round = Round::new();
+        round.add_ship(1, Ship::at_origin());
+        round.add_ship(2, Ship::at(Vector3::new(10.0, 0.0, 0.0)));
+        round.add_ship(3, Ship::at(Vector3::new(0.0, 10.0, 0.0)));
+        round.fast_forward(1); // let the collision world pick up the new ships
+
+        round.fire_weapon(1);
+        assert_eq!(vec![Event::Hit { shooter: 1, target: 2, damage: BEAM_DAMAGE }], round.drain_events());
+        round.fire_weapon(1);
+        assert!(round.drain_events().is_empty(), "weapon is cooling down");
+
+        let shots = (MAX_HULL / BEAM_DAMAGE) as u32;
+        for _ in 1..shots {
+            round.fast_forward(BEAM_COOLDOWN_TICKS);
+            round.fire_weapon(1);
+        }
+        let events = round.drain_events();
+        assert_eq!(Some(&Event::Destroyed { player: 2, by: Some(1) }), events.last());
+        assert!(round.board.ships.get(&2).is_none());
+        assert!(round.space.bodies.borrow().get(&2).is_none());
+        assert_eq!(MAX_HULL, round.board.ships.get(&3).expect("ship 3").hull());
+    }
+
+    #[test]
+    fn test_match_ends() {
+        let mut round = Round::new();
+        round.add_ship(1, Ship::at_origin());
+        round.add_ship(2, Ship::at(Vector3::new(10.0, 0.0, 0.0)));
+        round.set_mode(GameMode::LastShipStanding, Limits::none());
+        round.fast_forward(1);
+        for _ in 0..(MAX_HULL / BEAM_DAMAGE) as u32 {
+            round.fire_weapon(1);
+            round.fast_forward(BEAM_COOLDOWN_TICKS);
+        }
+        assert!(round.finished());
+        assert_eq!(vec![1], round.results().expect("match results").winners);
+        assert_eq!(1, round.scoreboard.score(1));
+
+        let time = round.board.time();
+        round.fast_forward(100);
+        assert_eq!(time, round.board.time(), "finished matches stand still");
+    }
+
+    #[test]
+    fn test_friendly_fire() {
+        let mut round = Round::new();
+        round.add_ship(1, Ship::at_origin());
+        round.add_ship(2, Ship::at(Vector3::new(10.0, 0.0, 0.0)));
+        for (id, team) in Team::defaults(2) {
+            round.board.add_team(id, team);
+        }
+        round.board.join_team(1, Some(1));
+        round.board.join_team(2, Some(1));
+        round.fast_forward(1);
+
+        round.friendly_fire = FriendlyFire::Off;
+        round.fire_weapon(1);
+        assert!(round.drain_events().is_empty(), "teammates can't hurt each other");
+        assert_eq!(MAX_HULL, round.board.ships.get(&2).expect("ship 2").hull());
+
+        round.friendly_fire = FriendlyFire::Reduced;
+        round.fast_forward(BEAM_COOLDOWN_TICKS);
+        round.fire_weapon(1);
+        let damage = FriendlyFire::Reduced.scale(BEAM_DAMAGE);
+        assert_eq!(vec![Event::Hit { shooter: 1, target: 2, damage: damage }], round.drain_events());
+        assert_eq!(MAX_HULL - damage, round.board.ships.get(&2).expect("ship 2").hull());
+    }
+
+    #[test]
+    fn test_beam_blocked() {
+        let mut round = Round::new();
+        round.add_ship(1, Ship::at_origin());
+        round.add_ship(2, Ship::at(Vector3::new(10.0, 0.0, 0.0)));
+        round.add_obstacle(Obstacle::Asteroid { position: Vector3::new(5.0, 0.0, 0.0), radius: 1.0 });
+        round.fast_forward(1);
+
+        round.fire_weapon(1);
+        assert!(round.drain_events().is_empty(), "asteroid soaks up the shot");
+    }
+
+    #[test]
+    fn test_line_of_sight() {
+        let mut round = Round::new();
+        round.add_ship(1, Ship::at_origin());
+        round.add_ship(2, Ship::at(Vector3::new(20.0, 0.0, 0.0)));
+        round.add_ship(3, Ship::at(Vector3::new(0.0, 20.0, 0.0)));
+        round.add_obstacle(Obstacle::Block {
+            position: Vector3::new(10.0, 0.0, 0.0),
+            half_extents: Vector3::new(1.0, 3.0, 3.0),
+        });
+        round.fast_forward(1);
+
+        assert!(!round.line_of_sight(1, 2));
+        assert!(!round.line_of_sight(2, 1));
+        assert!(round.line_of_sight(1, 3));
+
+        let view = round.sensor_view(1).expect("ship 1 view");
+        let seen: Vec<PlayerId> = view.contacts.iter().map(|c| c.player).collect();
+        assert_eq!(vec![3], seen);
+    }
+
+    #[test]
+    fn test_seeded_sensors() {
+        let noisy = |seed| {
+            let mut round = Round::new();
+            round.reseed(seed);
+            round.add_ship(1, Ship::at_origin());
+            round.add_ship(2, Ship::at(Vector3::new(120.0, 0.0, 0.0)));
+            round.fast_forward(1);
+            round
+        };
+        let round = noisy(5);
+        let view = round.sensor_view(1).expect("ship 1 view");
+        assert!(view.contacts[0].position != Vector3::new(120.0, 0.0, 0.0), "far away, so fuzzy");
+        assert_eq!(Some(view.clone()), round.sensor_view(1), "looking again doesn't change anything");
+        assert_eq!(Some(view.clone()), noisy(5).sensor_view(1), "same seed, same noise");
+        assert!(Some(view) != noisy(6).sensor_view(1));
+        assert_eq!(5, round.seed());
+    }
+
+    /// Two bots fighting around an asteroid
+    fn skirmish() -> Round {
+        let mut round = Round::new();
+        round.reseed(3);
+        round.add_obstacle(Obstacle::Asteroid { position: Vector3::new(0.0, 8.0, 0.0), radius: 3.0 });
+        round.add_ship(1, Ship::at(Vector3::new(-15.0, 0.0, 0.0)));
+        round.add_ship(2, Ship::at(Vector3::new(15.0, 0.0, 0.0)));
+        round.add_controller(1, Box::new(Bot::new(Tactic::Kiter, GameRng::new(1))));
+        round.add_controller(2, Box::new(Bot::new(Tactic::Dodger, GameRng::new(2))));
+        round
+    }
+
+    #[test]
+    fn test_state_hash() {
+        let mut a = skirmish();
+        let mut b = skirmish();
+        assert_eq!(a.state_hash(), b.state_hash());
+        a.fast_forward(100);
+        b.fast_forward(100);
+        assert_eq!(a.state_hash(), b.state_hash(), "same round, same hash");
+        assert_eq!(a.checkpoint(false), b.checkpoint(false));
+
+        b.fire_engine(2, Vector3::new(0.0, 0.0, 1e-3));
+        assert!(a.state_hash() != b.state_hash(), "physics counts, even before the board catches up");
+        b.fast_forward(1);
+        a.fast_forward(1);
+        let desync = a.checkpoint(true).verify(b.state_hash(), &b.board).expect("desync");
+        assert_eq!(1010, desync.time);
+        assert!(desync.diff.iter().any(|line| line.starts_with("ship 2")), "{}", desync);
+    }
+
+    #[test]
+    fn test_save_and_load() {
+        let mut straight = skirmish();
+        straight.fast_forward(400);
+
+        let mut saved = skirmish();
+        saved.fast_forward(150);
+        let bytes = saved.save();
+        let mut loaded = Round::load(&bytes).expect("loads");
+        assert_eq!(saved.board, loaded.board);
+        assert_eq!(saved.state_hash(), loaded.state_hash());
+        assert_eq!(SavedRound::from_bytes(&bytes).unwrap(), SavedRound::from_bytes(&loaded.save()).unwrap(),
+                   "nothing lost on the way");
+        for (player, controller) in saved.take_controllers() {
+            loaded.add_controller(player, controller);
+        }
+        loaded.fast_forward(250);
+        assert_eq!(straight.board, loaded.board, "picks up right where it left off");
+        assert_eq!(straight.scoreboard, loaded.scoreboard);
+        assert_eq!(straight.drain_events(), loaded.drain_events());
+
+        let mut newer = bytes.clone();
+        newer[0] = 9;
+        match Round::load(&newer).err() {
+            Some(SaveError::Version(9)) => (),
+            other => panic!("expected a version error, got {:?}", other),
+        }
+    }
+
     #[test]
     fn physics_even() {
         let mut round = Round::new();
         round.add_ship(1, Ship::at_origin());
         {
-            let ship = round.bodies.get(&1).expect("couldn't find ship");
+            let bodies = round.space.bodies.borrow();
+            let ship = bodies.get(&1).expect("couldn't find ship").borrow();
             assert!(ship.can_move());
             assert_eq!(ship.position_center(), Point::new(0.0, 0.0, 0.0));
             assert_eq!(ship.lin_vel(), Vector3::new(0.0, 0.0, 0.0));
-            // TODO: test position at origin
         }
 
         round.fire_engine(1, Vector3::new(1.0, 0.0, 0.0));
         round.tick_ahead(100); // run for 1 second
 
         {
-            let ship = round.bodies.get(&1).expect("couldn't find ship");
+            let bodies = round.space.bodies.borrow();
+            let ship = bodies.get(&1).expect("couldn't find ship").borrow();
             let vel = ship.lin_vel();
             assert!(vel.x > 0.0, "x vel greater than 0: {}", vel.x);
             assert_eq!(vel.y, 0.0, "y vel");
             assert_eq!(vel.z, 0.0, "z vel");
-            // TODO: test position moved
+            assert!(ship.position_center().x > 0.0, "ship moved along x");
         }
+        let ship = round.board.ships.get(&1).expect("ship on board");
+        assert!(ship.position().x > 0.0, "board synced with physics");
     }
 }
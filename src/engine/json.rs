@@ -0,0 +1,315 @@
+use na::Vector3;
+use serde_json;
+
+use engine::lifecycle::Phase;
+use engine::protocol::{ClientMessage, ServerMessage};
+use game::autopilot::Autopilot;
+use game::board::{PlayerId, TeamId, Timestep};
+use game::controller::{Commands, Contact, SensorView};
+use game::event::Event;
+use game::mode::Results;
+
+/// Longest line a JSON client or bot process can send, going past it ends the connection
+pub const MAX_LINE_BYTES: usize = 64 * 1024;
+
+/// JSON-friendly versions of the controller types, vectors are plain `[x, y, z]` arrays
+/// so bots in other languages don't need to know how nalgebra serializes itself
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct JsonContact {
+    pub player: PlayerId,
+    pub position: [f32; 3],
+    pub velocity: [f32; 3],
+    pub team: Option<TeamId>,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct JsonView {
+    pub player: PlayerId,
+    pub time: Timestep,
+    pub position: [f32; 3],
+    pub velocity: [f32; 3],
+    pub heading: [f32; 3],
+    pub hull: f32,
+    pub team: Option<TeamId>,
+    pub contacts: Vec<JsonContact>,
+}
+
+/// Any field can be left out, missing fields mean "do nothing"
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct JsonCommands {
+    #[serde(default)]
+    pub thrust: [f32; 3],
+    #[serde(default)]
+    pub torque: [f32; 3],
+    #[serde(default)]
+    pub fire: bool,
+    #[serde(default)]
+    pub autopilot: Option<JsonAutopilot>,
+    /// The time of the view these commands answer, bot processes have to send it back
+    #[serde(default)]
+    pub time: Option<Timestep>,
+}
+
+/// `Autopilot` orders, e.g. `{"goto": [10, 0, 0]}`, `"hold"` or
+/// `{"orbit": {"center": [0, 0, 0], "radius": 5}}`
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub enum JsonAutopilot {
+    #[serde(rename = "goto")]
+    GoTo([f32; 3]),
+    #[serde(rename = "match_velocity")]
+    MatchVelocity(PlayerId),
+    #[serde(rename = "hold")]
+    Hold,
+    #[serde(rename = "orbit")]
+    Orbit { center: [f32; 3], radius: f32 },
+}
+
+/// `ServerMessage` as sent to JSON clients, one message per line
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub enum JsonServerMessage {
+    #[serde(rename = "hello")]
+    Hello { player: PlayerId },
+    #[serde(rename = "snapshot")]
+    Snapshot { phase: Phase, view: JsonView },
+    #[serde(rename = "phase")]
+    Phase(Phase),
+    #[serde(rename = "event")]
+    Event(Event),
+    #[serde(rename = "results")]
+    Results(Results),
+    /// The hash as 16 hex digits, JSON numbers can't hold all 64 bits
+    #[serde(rename = "checkpoint")]
+    Checkpoint { time: Timestep, hash: String },
+}
+
+/// `ClientMessage` as sent by JSON clients, one message per line
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub enum JsonClientMessage {
+    #[serde(rename = "command")]
+    Command(JsonCommands),
+    #[serde(rename = "team")]
+    ChooseTeam(TeamId),
+}
+
+impl JsonServerMessage {
+    /// Serialize the message as a single newline-terminated line
+    pub fn to_line(&self) -> Vec<u8> {
+        let mut line = serde_json::to_vec(self).expect("Error serializing json message");
+        line.push(b'\n');
+        line
+    }
+}
+
+impl JsonClientMessage {
+    pub fn from_line(line: &str) -> Option<JsonClientMessage> {
+        serde_json::from_str(line).ok()
+    }
+}
+
+fn array(v: Vector3<f32>) -> [f32; 3] {
+    [v.x, v.y, v.z]
+}
+
+fn vector(a: [f32; 3]) -> Vector3<f32> {
+    Vector3::new(a[0], a[1], a[2])
+}
+
+impl<'a> From<&'a Contact> for JsonContact {
+    fn from(contact: &Contact) -> JsonContact {
+        JsonContact {
+            player: contact.player,
+            position: array(contact.position),
+            velocity: array(contact.velocity),
+            team: contact.team,
+        }
+    }
+}
+
+impl<'a> From<&'a SensorView> for JsonView {
+    fn from(view: &SensorView) -> JsonView {
+        JsonView {
+            player: view.player,
+            time: view.time,
+            position: array(view.position),
+            velocity: array(view.velocity),
+            heading: array(view.heading()),
+            hull: view.hull,
+            team: view.team,
+            contacts: view.contacts.iter().map(JsonContact::from).collect(),
+        }
+    }
+}
+
+impl<'a> From<&'a ServerMessage> for JsonServerMessage {
+    fn from(message: &ServerMessage) -> JsonServerMessage {
+        match *message {
+            ServerMessage::Hello { player } => JsonServerMessage::Hello { player: player },
+            ServerMessage::Snapshot { phase, ref view } => {
+                JsonServerMessage::Snapshot { phase: phase, view: JsonView::from(view) }
+            },
+            ServerMessage::Phase(phase) => JsonServerMessage::Phase(phase),
+            ServerMessage::Event(ref event) => JsonServerMessage::Event(event.clone()),
+            ServerMessage::Results(ref results) => JsonServerMessage::Results(results.clone()),
+            ServerMessage::Checkpoint { time, hash } => {
+                JsonServerMessage::Checkpoint { time: time, hash: format!("{:016x}", hash) }
+            },
+        }
+    }
+}
+
+impl From<JsonClientMessage> for ClientMessage {
+    fn from(message: JsonClientMessage) -> ClientMessage {
+        match message {
+            JsonClientMessage::Command(commands) => ClientMessage::Command(Commands::from(commands)),
+            JsonClientMessage::ChooseTeam(team) => ClientMessage::ChooseTeam(team),
+        }
+    }
+}
+
+impl From<JsonCommands> for Commands {
+    fn from(commands: JsonCommands) -> Commands {
+        Commands {
+            thrust: vector(commands.thrust),
+            torque: vector(commands.torque),
+            fire: commands.fire,
+            autopilot: commands.autopilot.map(Autopilot::from),
+        }
+    }
+}
+
+impl<'a> From<&'a Commands> for JsonCommands {
+    fn from(commands: &Commands) -> JsonCommands {
+        JsonCommands {
+            thrust: array(commands.thrust),
+            torque: array(commands.torque),
+            fire: commands.fire,
+            autopilot: commands.autopilot.as_ref().map(JsonAutopilot::from),
+            time: None,
+        }
+    }
+}
+
+impl From<JsonAutopilot> for Autopilot {
+    fn from(order: JsonAutopilot) -> Autopilot {
+        match order {
+            JsonAutopilot::GoTo(point) => Autopilot::GoTo(vector(point)),
+            JsonAutopilot::MatchVelocity(player) => Autopilot::MatchVelocity(player),
+            JsonAutopilot::Hold => Autopilot::Hold,
+            JsonAutopilot::Orbit { center, radius } => {
+                Autopilot::Orbit { center: vector(center), radius: radius }
+            },
+        }
+    }
+}
+
+impl<'a> From<&'a Autopilot> for JsonAutopilot {
+    fn from(order: &Autopilot) -> JsonAutopilot {
+        match *order {
+            Autopilot::GoTo(point) => JsonAutopilot::GoTo(array(point)),
+            Autopilot::MatchVelocity(player) => JsonAutopilot::MatchVelocity(player),
+            Autopilot::Hold => JsonAutopilot::Hold,
+            Autopilot::Orbit { center, radius } => {
+                JsonAutopilot::Orbit { center: array(center), radius: radius }
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json;
+    use game::board::Board;
+    use game::ship::Ship;
+    use super::*;
+
+    #[test]
+    fn test_view() {
+        let mut board = Board::new();
+        board.add_ship(1, Ship::at_origin());
+        board.add_ship(2, Ship::at(Vector3::new(0.0, 4.0, 0.0)));
+        let view = SensorView::from_board(&board, 1).unwrap();
+
+        let json = JsonView::from(&view);
+        assert_eq!([1.0, 0.0, 0.0], json.heading);
+        assert_eq!(1, json.contacts.len());
+        assert_eq!([0.0, 4.0, 0.0], json.contacts[0].position);
+        let line = serde_json::to_string(&json).unwrap();
+        assert!(!line.contains('\n'), "views fit on a single line");
+    }
+
+    #[test]
+    fn test_server_messages() {
+        let hello = JsonServerMessage::from(&ServerMessage::Hello { player: 7 });
+        assert_eq!(b"{\"hello\":{\"player\":7}}\n".to_vec(), hello.to_line());
+
+        let mut board = Board::new();
+        board.add_ship(2, Ship::at(Vector3::new(1.0, 2.0, 3.0)));
+        board.add_ship(1, Ship::at_origin());
+        let view = SensorView::from_board(&board, 2).unwrap();
+        match JsonServerMessage::from(&ServerMessage::Snapshot { phase: Phase::Live, view: view }) {
+            JsonServerMessage::Snapshot { phase, view } => {
+                assert_eq!(Phase::Live, phase);
+                assert_eq!(2, view.player);
+                assert_eq!([1.0, 2.0, 3.0], view.position);
+                assert_eq!(1, view.contacts[0].player);
+            },
+            other => panic!("expected a snapshot, got {:?}", other),
+        }
+
+        let countdown = ServerMessage::Snapshot {
+            phase: Phase::Countdown { remaining_ms: 3000 },
+            view: SensorView::from_board(&board, 1).unwrap(),
+        };
+        let line = String::from_utf8(JsonServerMessage::from(&countdown).to_line()).unwrap();
+        assert!(line.contains(r#""phase":{"countdown":{"remaining_ms":3000}}"#), "got {}", line);
+
+        let checkpoint = ServerMessage::Checkpoint { time: 1000, hash: 0xdead_beef_0000_0001 };
+        let line = String::from_utf8(JsonServerMessage::from(&checkpoint).to_line()).unwrap();
+        assert_eq!("{\"checkpoint\":{\"time\":1000,\"hash\":\"deadbeef00000001\"}}\n", line);
+    }
+
+    #[test]
+    fn test_client_messages() {
+        let line = r#"{"command": {"thrust": [0, 0, 1], "fire": true}}"#;
+        let message = JsonClientMessage::from_line(line).expect("parsed command");
+        match ClientMessage::from(message) {
+            ClientMessage::Command(commands) => {
+                assert_eq!(Vector3::new(0.0, 0.0, 1.0), commands.thrust);
+                assert!(commands.fire);
+            },
+            other => panic!("expected a command, got {:?}", other),
+        }
+        let message = JsonClientMessage::from_line(r#"{"team": 2}"#).expect("parsed team choice");
+        assert_eq!(ClientMessage::ChooseTeam(2), ClientMessage::from(message));
+        assert!(JsonClientMessage::from_line("not json").is_none());
+    }
+
+    #[test]
+    fn test_partial_commands() {
+        let parsed: JsonCommands = serde_json::from_str(r#"{"fire": true}"#).unwrap();
+        let commands = Commands::from(parsed);
+        assert!(commands.fire);
+        assert_eq!(Vector3::new(0.0, 0.0, 0.0), commands.thrust);
+
+        let parsed: JsonCommands = serde_json::from_str(r#"{"thrust": [1, 2, 3]}"#).unwrap();
+        let commands = Commands::from(parsed);
+        assert_eq!(Vector3::new(1.0, 2.0, 3.0), commands.thrust);
+        assert_eq!(None, commands.autopilot);
+    }
+
+    #[test]
+    fn test_autopilot() {
+        let parsed: JsonCommands = serde_json::from_str(r#"{"autopilot": {"goto": [1, 2, 3]}}"#).unwrap();
+        assert_eq!(Some(Autopilot::GoTo(Vector3::new(1.0, 2.0, 3.0))), Commands::from(parsed).autopilot);
+
+        let parsed: JsonCommands = serde_json::from_str(r#"{"autopilot": "hold"}"#).unwrap();
+        assert_eq!(Some(Autopilot::Hold), Commands::from(parsed).autopilot);
+
+        let orbit = Autopilot::Orbit { center: Vector3::new(0.0, 0.0, 5.0), radius: 10.0 };
+        let mut commands = Commands::none();
+        commands.autopilot = Some(orbit.clone());
+        let round_trip = Commands::from(JsonCommands::from(&commands));
+        assert_eq!(Some(orbit), round_trip.autopilot);
+    }
+}
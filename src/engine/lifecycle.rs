@@ -0,0 +1,349 @@
+use std::collections::BTreeSet;
+use std::path::PathBuf;
+
+use time;
+
+use engine::engine::{Round, TICKS_TO_MS};
+use game::board::PlayerId;
+
+/// Where a match is in its lifecycle, sent to clients with every snapshot
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+pub enum Phase {
+    /// Waiting for enough players to start
+    #[serde(rename = "lobby")]
+    Lobby,
+    /// About to start, nothing moves yet
+    #[serde(rename = "countdown")]
+    Countdown { remaining_ms: u32 },
+    /// The round is running
+    #[serde(rename = "live")]
+    Live,
+    /// The match is over, a fresh round starts once this runs out
+    #[serde(rename = "results")]
+    Results { remaining_ms: u32 },
+}
+
+#[derive(Clone, Debug)]
+pub struct MatchConfig {
+    /// Remote players needed before the countdown starts, bots and pilots on the server don't count
+    pub min_players: usize,
+    pub countdown_ms: u32,
+    /// How long the results stay up before the next round
+    pub results_ms: u32,
+    /// Directory a replay of every round is saved in, named after the round's seed
+    /// Replays are rewritten every `REPLAY_SAVE_MS` of play, so a killed server loses at most that much
+    pub replays: Option<PathBuf>,
+}
+
+impl Default for MatchConfig {
+    fn default() -> MatchConfig {
+        MatchConfig {
+            min_players: 2,
+            countdown_ms: 5000,
+            results_ms: 10_000,
+            replays: None,
+        }
+    }
+}
+
+/// How much play goes by between saves of the replay in progress
+pub const REPLAY_SAVE_MS: u32 = 60_000;
+
+/// Runs rounds back to back: lobby, countdown, live, results, then a fresh round
+pub struct Match {
+    pub round: Round,
+    config: MatchConfig,
+    /// Builds each new round, controllers are carried over from the last one
+    setup: Box<Fn() -> Round>,
+    phase: Phase,
+    /// Remote players flying a ship, see `join`
+    players: BTreeSet<PlayerId>,
+    /// Live time that hasn't added up to a whole physics step yet
+    pending_ms: u32,
+    /// Round time the replay in progress was last saved at
+    saved_at: u32,
+    last_update: f64,
+}
+
+impl Match {
+    pub fn new(config: MatchConfig, setup: Box<Fn() -> Round>) -> Match {
+        Match {
+            round: setup(),
+            config: config,
+            setup: setup,
+            phase: Phase::Lobby,
+            players: BTreeSet::new(),
+            pending_ms: 0,
+            saved_at: 0,
+            last_update: time::precise_time_s(),
+        }
+    }
+
+    pub fn phase(&self) -> Phase {
+        self.phase
+    }
+
+    /// A remote player took the controls of `player`'s ship
+    pub fn join(&mut self, player: PlayerId) {
+        self.players.insert(player);
+    }
+
+    pub fn leave(&mut self, player: PlayerId) {
+        self.players.remove(&player);
+    }
+
+    fn ready(&self) -> bool {
+        self.players.len() >= self.config.min_players
+    }
+
+    /// Move the match along by however much time has passed since the last call
+    pub fn tick(&mut self) {
+        let now = time::precise_time_s();
+        let ms = ((now - self.last_update) * 1000.0) as u32;
+        self.last_update += ms as f64 / 1000.0;
+        self.advance(ms);
+    }
+
+    /// Move the match along by `ms`, at most one phase change per call
+    pub fn advance(&mut self, ms: u32) {
+        let phase = self.phase;
+        self.phase = match phase {
+            Phase::Lobby if self.ready() => Phase::Countdown { remaining_ms: self.config.countdown_ms },
+            Phase::Lobby => Phase::Lobby,
+            Phase::Countdown { .. } if !self.ready() => Phase::Lobby,
+            Phase::Countdown { remaining_ms } if ms >= remaining_ms => {
+                self.pending_ms = 0;
+                if self.config.replays.is_some() {
+                    self.round.record();
+                    self.saved_at = self.round.board.time();
+                }
+                Phase::Live
+            },
+            Phase::Countdown { remaining_ms } => Phase::Countdown { remaining_ms: remaining_ms - ms },
+            Phase::Live => {
+                self.pending_ms += ms;
+                self.round.fast_forward(self.pending_ms / TICKS_TO_MS);
+                self.pending_ms %= TICKS_TO_MS;
+                if self.round.finished() {
+                    self.finish_replay();
+                    Phase::Results { remaining_ms: self.config.results_ms }
+                } else if self.round.board.time() >= self.saved_at + REPLAY_SAVE_MS {
+                    self.save_replay();
+                    Phase::Live
+                } else {
+                    Phase::Live
+                }
+            },
+            Phase::Results { remaining_ms } if ms >= remaining_ms => {
+                self.reset();
+                Phase::Lobby
+            },
+            Phase::Results { remaining_ms } => Phase::Results { remaining_ms: remaining_ms - ms },
+        };
+    }
+
+    /// Write out everything recorded of the round so far, over any earlier save of it
+    fn save_replay(&mut self) -> Option<PathBuf> {
+        self.saved_at = self.round.board.time();
+        let (dir, replay) = match (self.config.replays.as_ref(), self.round.recording()) {
+            (Some(dir), Some(replay)) => (dir, replay),
+            _ => return None,
+        };
+        let path = dir.join(format!("round-{}.replay", replay.seed()));
+        match replay.save(&path) {
+            Ok(()) => Some(path),
+            Err(e) => {
+                println!("Couldn't save replay to {}: {}", path.display(), e);
+                None
+            },
+        }
+    }
+
+    /// Save the replay one last time and stop recording
+    fn finish_replay(&mut self) {
+        if let Some(path) = self.save_replay() {
+            println!("Saved replay to {}", path.display());
+        }
+        self.round.take_recording();
+    }
+
+    /// Swap in a fresh round, everyone still at the controls stays on their team and gets a new ship
+    /// Each round is seeded with the seed after the last one's
+    fn reset(&mut self) {
+        self.finish_replay();
+        let controllers = self.round.take_controllers();
+        let members = self.round.board.members.clone();
+        let seed = self.round.seed().wrapping_add(1);
+        self.round = (self.setup)();
+        self.round.reseed(seed);
+        for (player, controller) in controllers {
+            if self.round.board.join_team(player, members.get(&player).cloned()).is_none() {
+                self.round.board.join_team(player, None);
+            }
+            self.round.spawn_ship(player);
+            self.round.add_controller(player, controller);
+        }
+    }
+}
+
+/// A round cut short by the server going down still gets its replay
+impl Drop for Match {
+    fn drop(&mut self) {
+        self.finish_replay();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::env;
+    use std::fs;
+    use na::Vector3;
+    use engine::replay::Replay;
+    use game::controller::{Commands, Fault, SensorView, ShipController};
+    use game::mode::{GameMode, Limits};
+    use game::board::Team;
+    use game::scanner::Scanner;
+    use game::ship::Ship;
+    use super::*;
+
+    struct Idle;
+
+    impl ShipController for Idle {
+        fn tick(&mut self, _view: &SensorView, _scanner: &Scanner) -> Result<Commands, Fault> {
+            Ok(Commands::none())
+        }
+    }
+
+    fn config() -> MatchConfig {
+        MatchConfig { min_players: 2, countdown_ms: 1000, results_ms: 2000, replays: None }
+    }
+
+    /// Two ships, and a match that's over as soon as 500ms have been played
+    fn setup() -> Round {
+        let mut round = Round::new();
+        round.add_ship(1, Ship::at(Vector3::new(-10.0, 0.0, 0.0)));
+        round.add_ship(2, Ship::at(Vector3::new(10.0, 0.0, 0.0)));
+        round.set_mode(GameMode::Deathmatch, Limits { time: Some(500), score: None });
+        round
+    }
+
+    #[test]
+    fn test_lobby_waits_for_players() {
+        let mut game = Match::new(config(), Box::new(setup));
+        game.round.add_controller(1, Box::new(Idle));
+        game.round.add_controller(2, Box::new(Idle));
+        game.join(1);
+        game.advance(5000);
+        assert_eq!(Phase::Lobby, game.phase(), "bots don't count");
+
+        game.join(2);
+        game.advance(10);
+        assert_eq!(Phase::Countdown { remaining_ms: 1000 }, game.phase());
+        game.advance(400);
+        assert_eq!(Phase::Countdown { remaining_ms: 600 }, game.phase());
+        assert_eq!(0, game.round.board.time(), "nothing moves before the match starts");
+
+        game.leave(2);
+        game.advance(10);
+        assert_eq!(Phase::Lobby, game.phase(), "countdown is called off if a player leaves");
+    }
+
+    #[test]
+    fn test_full_cycle() {
+        let mut game = Match::new(config(), Box::new(setup));
+        game.round.add_controller(1, Box::new(Idle));
+        game.round.add_controller(2, Box::new(Idle));
+        game.join(1);
+        game.join(2);
+        game.advance(0);
+        game.advance(1000);
+        assert_eq!(Phase::Live, game.phase());
+
+        game.advance(255);
+        assert_eq!(250, game.round.board.time(), "whole physics steps only");
+        game.advance(255);
+        assert_eq!(Phase::Results { remaining_ms: 2000 }, game.phase());
+        assert!(game.round.results().is_some());
+
+        game.advance(2000);
+        assert_eq!(Phase::Lobby, game.phase());
+        assert_eq!(0, game.round.board.time(), "a fresh round");
+        assert!(game.round.results().is_none());
+        assert!(game.round.has_controller(1) && game.round.has_controller(2), "controllers carry over");
+        assert_eq!(1, game.round.seed(), "the next seed along");
+        assert_eq!(2, game.round.board.ships.len());
+    }
+
+    #[test]
+    fn test_saves_replays() {
+        let dir = env::temp_dir().join("pewpew-test-replays");
+        fs::create_dir_all(&dir).unwrap();
+        let mut game = Match::new(MatchConfig { replays: Some(dir.clone()), ..config() }, Box::new(setup));
+        game.round.reseed(31);
+        game.round.add_controller(1, Box::new(Idle));
+        game.round.add_controller(2, Box::new(Idle));
+        game.join(1);
+        game.join(2);
+        game.advance(0);
+        game.advance(1000);
+        game.advance(500);
+        assert_eq!(Phase::Results { remaining_ms: 2000 }, game.phase());
+
+        let replay = Replay::load(&dir.join("round-31.replay")).expect("saved replay");
+        assert_eq!(50, replay.frames.len());
+        assert!(game.round.recording().is_none());
+    }
+
+    #[test]
+    fn test_saves_replays_as_it_goes() {
+        let dir = env::temp_dir().join("pewpew-test-replays-as-it-goes");
+        fs::create_dir_all(&dir).unwrap();
+        let _ = fs::remove_file(dir.join("round-32.replay")); // left over from an earlier run
+        let endless = || {
+            let mut round = setup();
+            round.set_mode(GameMode::Deathmatch, Limits::none());
+            round
+        };
+        let mut game = Match::new(MatchConfig { replays: Some(dir.clone()), ..config() }, Box::new(endless));
+        game.round.reseed(32);
+        game.join(1);
+        game.join(2);
+        game.advance(0);
+        game.advance(1000);
+        game.advance(REPLAY_SAVE_MS - 10);
+        assert!(Replay::load(&dir.join("round-32.replay")).is_err(), "not yet");
+        game.advance(10);
+        let replay = Replay::load(&dir.join("round-32.replay")).expect("saved replay");
+        assert_eq!((REPLAY_SAVE_MS / TICKS_TO_MS) as usize, replay.frames.len());
+
+        game.advance(500);
+        drop(game);
+        let replay = Replay::load(&dir.join("round-32.replay")).expect("saved replay");
+        assert_eq!((REPLAY_SAVE_MS / TICKS_TO_MS) as usize + 50, replay.frames.len(), "saved on the way out");
+    }
+
+    #[test]
+    fn test_teams_carry_over() {
+        let with_teams = || {
+            let mut round = setup();
+            for (id, team) in Team::defaults(2) {
+                round.board.add_team(id, team);
+            }
+            round
+        };
+        let mut game = Match::new(config(), Box::new(with_teams));
+        game.round.board.join_team(1, Some(2));
+        game.round.board.join_team(2, Some(2));
+        game.round.add_controller(1, Box::new(Idle));
+        game.round.add_controller(2, Box::new(Idle));
+        game.join(1);
+        game.join(2);
+        game.advance(0);
+        game.advance(1000);
+        game.advance(500);
+        game.advance(2000);
+        assert_eq!(Phase::Lobby, game.phase());
+        assert_eq!(Some(2), game.round.board.team_of(1));
+        assert_eq!(Some(2), game.round.board.team_of(2));
+    }
+}
@@ -1,5 +1,18 @@
+pub mod bots;
 pub mod client;
+pub mod desync;
 pub mod engine;
 pub mod graphics;
+pub mod json;
+pub mod lifecycle;
 pub mod networking;
+pub mod pilots;
+pub mod protocol;
+pub mod replay;
+pub mod save;
+pub mod script;
+pub mod sim;
+pub mod spatial;
+pub mod subprocess;
+pub mod wasm;
 mod frame;
@@ -1,70 +1,301 @@
 use std::cell::RefCell;
 use std::collections::HashMap;
-use std::io::Write;
-use std::ops::Deref;
+use std::io::{Error, ErrorKind, Write};
+use std::net::SocketAddr;
 use std::rc::Rc;
 use std::str;
 use std::time::Duration;
 
-use bytes::{ByteOrder, LittleEndian};
+use bytes::{BytesMut, ByteOrder, LittleEndian};
 use futures;
 use futures::{Future};
 use futures::stream::Stream;
-use tokio_core::net::TcpListener;
-use tokio_core::reactor::{Core, Interval};
+use futures::sync::mpsc::UnboundedSender;
+use tokio_core::net::{TcpListener, TcpStream};
+use tokio_core::reactor::{Core, Handle, Interval};
+use tokio_io::codec::{length_delimited, Decoder, FramedRead};
 use tokio_io::io;
 use tokio_io::{AsyncRead};
 
-use game::board::Board;
+use engine::engine::Round;
+use engine::json::{JsonClientMessage, JsonServerMessage, MAX_LINE_BYTES};
+use engine::lifecycle::{Match, Phase};
+use engine::protocol::{ClientMessage, ServerMessage};
+use engine::script::PilotWatcher;
+use game::board::PlayerId;
+use game::controller::{Commands, Fault, SensorView, ShipController};
+use game::event::Event;
+use game::scanner::Scanner;
 
-pub fn launch_server(board: Board) {
-    let addr = "127.0.0.1:8888".parse().unwrap();
-    println!("Started and listening on {}", addr);
-    let mut core = Core::new().unwrap();
-    let handle = core.handle();
-    let socket = TcpListener::bind(&addr, &handle).unwrap();
+/// How often clients are sent the round's state hash, in board time
+pub const CHECKPOINT_INTERVAL_MS: u32 = 1000;
+
+/// Wire formats the server speaks, each on its own port
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Format {
+    /// bincode messages behind a 4 byte little endian length
+    Bincode,
+    /// one JSON message per line
+    Json,
+}
 
-    let connections = Rc::new(RefCell::new(HashMap::new()));
-    let connections1 = connections.clone();
+struct Connection {
+    player: PlayerId,
+    format: Format,
+    tx: UnboundedSender<Vec<u8>>,
+    /// Set when this connection is the one flying the player's ship
+    commands: Option<Rc<RefCell<Commands>>>,
+}
 
-    let srv = socket.incoming().for_each(move |(stream, addr)| {
-        println!("New Connection: {}", addr);
-        let (_, writer) = stream.split();
+impl Connection {
+    fn send(&self, message: &ServerMessage) {
+        let bytes = match self.format {
+            Format::Bincode => message.to_frame(),
+            Format::Json => JsonServerMessage::from(message).to_line(),
+        };
+        if let Err(e) = self.tx.send(bytes) {
+            println!("Couldn't queue message for {}: {}", self.player, e);
+        }
+    }
+}
 
-        let (tx, rx) = futures::sync::mpsc::unbounded::<Vec<u8>>();
-        connections1.borrow_mut().insert(addr, tx);
+struct Server {
+    game: RefCell<Match>,
+    pilots: RefCell<PilotWatcher>,
+    connections: RefCell<HashMap<SocketAddr, Connection>>,
+}
 
-        // TODO: read from connections too, close when they EOF
+/// Flies a ship with whatever commands its client sent last
+struct RemoteController {
+    commands: Rc<RefCell<Commands>>,
+}
 
-        let socket_writer = rx.fold(writer, |writer, msg| {
-            // TODO: let len = msg.len();
-            let amt = io::write_all(writer, msg);
-            let amt = amt.map(|(writer, _)| writer);
-            amt.map_err(|_| ())
-        });
+impl ShipController for RemoteController {
+    fn tick(&mut self, _view: &SensorView, _scanner: &Scanner) -> Result<Commands, Fault> {
+        let mut commands = self.commands.borrow_mut();
+        let current = commands.clone();
+        commands.fire = false; // thrust is held until replaced, but each shot is only sent once
+        Ok(current)
+    }
+}
 
-        let connections = connections1.clone();
-        handle.spawn(socket_writer.then(move |_| {
-            connections.borrow_mut().remove(&addr);
-            println!("Connection {} closed.", addr);
-            Ok(())
-        }));
+/// Run `game` and broadcast snapshots to every connected client, blocks until shutdown
+/// Bincode clients connect on port 8888, JSON clients on 8889
+/// Pilot scripts changed on disk are swapped in between ticks
+pub fn launch_server(game: Match, pilots: PilotWatcher) {
+    let bincode_addr = "127.0.0.1:8888".parse().unwrap();
+    let json_addr = "127.0.0.1:8889".parse().unwrap();
+    println!("Started and listening on {} (bincode) and {} (json)", bincode_addr, json_addr);
+    let mut core = Core::new().unwrap();
+    let handle = core.handle();
 
-        Ok(())
+    let server = Rc::new(Server {
+        game: RefCell::new(game),
+        pilots: RefCell::new(pilots),
+        connections: RefCell::new(HashMap::new()),
     });
 
-    let handle = core.handle();
+    let bincode_srv = listen(&bincode_addr, Format::Bincode, server.clone(), handle.clone());
+    let json_srv = listen(&json_addr, Format::Json, server.clone(), handle.clone());
+
     let interval = Interval::new(Duration::from_millis(50), &handle).unwrap();
     let heartbeat = interval.for_each(move |_| {
-        for (_, tx) in connections.borrow().deref() {
-            let board_bytes = board.to_bytes();
-            let to_send = len_encode_bytes(board_bytes);
-            tx.send(to_send).unwrap();
-        }
+        heartbeat(&server);
         futures::future::ok(())
     });
 
-    core.run(srv.join(heartbeat)).unwrap();
+    core.run(bincode_srv.join(json_srv).join(heartbeat)).unwrap();
+}
+
+fn listen(addr: &SocketAddr, format: Format, server: Rc<Server>, handle: Handle)
+          -> Box<Future<Item = (), Error = ::std::io::Error>> {
+    let socket = TcpListener::bind(addr, &handle).unwrap();
+    Box::new(socket.incoming().for_each(move |(stream, addr)| {
+        accept(&server, stream, addr, format, &handle);
+        Ok(())
+    }))
+}
+
+fn accept(server: &Rc<Server>, stream: TcpStream, addr: SocketAddr, format: Format, handle: &Handle) {
+    let (reader, writer) = stream.split();
+
+    let (tx, rx) = futures::sync::mpsc::unbounded::<Vec<u8>>();
+    let player = next_player(&server.connections.borrow(), &server.game.borrow().round);
+    println!("New {:?} Connection: {} flying ship {}", format, addr, player);
+
+    // players already flown by a pilot on the server can watch, but not send commands
+    let commands = {
+        let mut game = server.game.borrow_mut();
+        if game.round.has_controller(player) {
+            None
+        } else {
+            let commands = Rc::new(RefCell::new(Commands::none()));
+            game.round.board.join_team(player, None);
+            game.round.spawn_ship(player);
+            game.round.add_controller(player, Box::new(RemoteController { commands: commands.clone() }));
+            game.join(player);
+            Some(commands)
+        }
+    };
+
+    let connection = Connection { player: player, format: format, tx: tx, commands: commands };
+    connection.send(&ServerMessage::Hello { player: player });
+    server.connections.borrow_mut().insert(addr, connection);
+
+    let messages: Box<Stream<Item = ClientMessage, Error = ::std::io::Error>> = match format {
+        Format::Bincode => {
+            let frames = length_delimited::Builder::new()
+                .little_endian()
+                .length_field_length(4)
+                .new_read(reader);
+            Box::new(frames.filter_map(|frame| ClientMessage::from_bytes(&frame)))
+        },
+        Format::Json => {
+            let lines = FramedRead::new(reader, LineCodec);
+            Box::new(lines.filter_map(|line| JsonClientMessage::from_line(&line).map(ClientMessage::from)))
+        },
+    };
+
+    let receiver = server.clone();
+    let socket_reader = messages.for_each(move |message| {
+        receive(&receiver, &addr, message);
+        Ok(())
+    });
+    let closer = server.clone();
+    handle.spawn(socket_reader.then(move |_| {
+        close(&closer, &addr);
+        Ok(())
+    }));
+
+    let socket_writer = rx.fold(writer, |writer, msg| {
+        let amt = io::write_all(writer, msg);
+        let amt = amt.map(|(writer, _)| writer);
+        amt.map_err(|_| ())
+    });
+    let closer = server.clone();
+    handle.spawn(socket_writer.then(move |_| {
+        close(&closer, &addr);
+        Ok(())
+    }));
+}
+
+fn receive(server: &Server, addr: &SocketAddr, message: ClientMessage) {
+    let connections = server.connections.borrow();
+    let connection = match connections.get(addr) {
+        Some(connection) => connection,
+        None => return,
+    };
+    let commands = connection.commands.as_ref();
+    match message {
+        ClientMessage::Command(new_commands) => {
+            commands.map(|commands| *commands.borrow_mut() = new_commands);
+        },
+        ClientMessage::ChooseTeam(team) => {
+            let mut game = server.game.borrow_mut();
+            // a team that doesn't exist is ignored, the player stays where they were
+            if commands.is_some() && game.phase() == Phase::Lobby
+                && game.round.board.join_team(connection.player, Some(team)).is_none() {
+                println!("Player {} asked for team {}, which doesn't exist", connection.player, team);
+            }
+        },
+    }
+}
+
+/// Forget a connection, its ship drifts on with no one at the controls
+fn close(server: &Server, addr: &SocketAddr) {
+    let connection = server.connections.borrow_mut().remove(addr);
+    if let Some(connection) = connection {
+        if connection.commands.is_some() {
+            let mut game = server.game.borrow_mut();
+            game.round.remove_controller(connection.player);
+            game.leave(connection.player);
+        }
+        println!("Connection {} closed.", addr);
+    }
+}
+
+fn heartbeat(server: &Server) {
+    let mut game = server.game.borrow_mut();
+
+    // swap in reloaded pilots on the tick boundary, before the round advances
+    // compile errors go to whoever is connected as the pilot's player, and the server's log
+    for (player, pilot) in server.pilots.borrow_mut().poll() {
+        match pilot {
+            Ok(pilot) => {
+                game.round.add_controller(player, Box::new(pilot));
+                broadcast(server, &ServerMessage::Event(Event::PilotReloaded { player: player }));
+            },
+            Err(e) => {
+                println!("Pilot for player {} rejected: {}", player, e);
+                let rejected = ServerMessage::Event(Event::PilotRejected { player: player, error: e.to_string() });
+                for connection in server.connections.borrow().values().filter(|c| c.player == player) {
+                    connection.send(&rejected);
+                }
+            },
+        }
+    }
+
+    let was_live = game.phase() == Phase::Live;
+    let before = game.round.board.time();
+    game.tick();
+    let phase = game.phase();
+    let time = game.round.board.time();
+
+    // every client only sees what its own ship's sensors pick up, those without a ship (watching,
+    // or shot down) still hear where the match is at
+    for connection in server.connections.borrow().values() {
+        match game.round.sensor_view(connection.player) {
+            Some(view) => connection.send(&ServerMessage::Snapshot { phase: phase, view: view }),
+            None => connection.send(&ServerMessage::Phase(phase)),
+        }
+    }
+    for event in game.round.drain_events() {
+        broadcast(server, &ServerMessage::Event(event));
+    }
+    if phase == Phase::Live && time / CHECKPOINT_INTERVAL_MS != before / CHECKPOINT_INTERVAL_MS {
+        broadcast(server, &ServerMessage::Checkpoint { time: time, hash: game.round.state_hash() });
+    }
+    if was_live && phase != Phase::Live {
+        if let Some(results) = game.round.results() {
+            broadcast(server, &ServerMessage::Results(results.clone()));
+        }
+    }
+}
+
+/// The lowest ship id that isn't already claimed by a connection, a bot or a pilot
+fn next_player(connections: &HashMap<SocketAddr, Connection>, round: &Round) -> PlayerId {
+    (1..PlayerId::max_value())
+        .find(|id| connections.values().all(|c| c.player != *id) && !round.has_controller(*id))
+        .expect("out of player ids")
+}
+
+/// Splits what a JSON client sends into lines, and fails once a line gets too long
+struct LineCodec;
+
+impl Decoder for LineCodec {
+    type Item = String;
+    type Error = Error;
+
+    fn decode(&mut self, buf: &mut BytesMut) -> Result<Option<String>, Error> {
+        let end = match buf.iter().position(|b| *b == b'\n') {
+            Some(end) => end,
+            None if buf.len() > MAX_LINE_BYTES => return Err(Error::new(ErrorKind::InvalidData, "line too long")),
+            None => return Ok(None),
+        };
+        if end > MAX_LINE_BYTES {
+            return Err(Error::new(ErrorKind::InvalidData, "line too long"));
+        }
+        let line = buf.split_to(end + 1);
+        str::from_utf8(&line[..end])
+            .map(|line| Some(line.trim_right_matches('\r').to_string()))
+            .map_err(|e| Error::new(ErrorKind::InvalidData, e))
+    }
+}
+
+fn broadcast(server: &Server, message: &ServerMessage) {
+    for connection in server.connections.borrow().values() {
+        connection.send(message);
+    }
 }
 
 /// Prepend a vec of bytes with it's length (4 bytes little endian)
@@ -80,55 +311,145 @@ pub fn len_encode_bytes(mut to_write: Vec<u8>) -> Vec<u8> {
 
 #[cfg(test)]
 mod test {
-    use std::io::{Read, Write};
+    use std::io::{BufRead, BufReader, Read, Write};
     use std::thread;
     use std::net::TcpStream;
     use std::time::Duration;
+    use serde_json;
     use super::*;
 
     use engine::engine::Round;
+    use engine::json::JsonServerMessage;
+    use engine::lifecycle::MatchConfig;
+    use engine::script::Budget;
+    use engine::spatial::Space;
+    use game::board::Board;
     use game::ship::Ship;
 
     #[test]
     fn test_echo_server() {
-        let mut round = Round::new();
-        round.board.add_ship(1, Ship::at_origin());
-        round.board.add_ship(2, Ship::at_origin());
-        let board_bytes = len_encode_bytes(round.board.to_bytes());
-        let board = round.board;
-        thread::spawn(|| { launch_server(board); });
+        let mut board = Board::new();
+        board.add_ship(1, Ship::at_origin());
+        board.add_ship(2, Ship::at_origin());
+        let expected = board.clone();
+        thread::spawn(move || {
+            let config = MatchConfig { min_players: 1, countdown_ms: 0, results_ms: 1000, replays: None };
+            let game = Match::new(config, Box::new(move || {
+                let mut round = Round::new();
+                round.board = board.clone(); // ships with no physics bodies stay put
+                round
+            }));
+            launch_server(game, PilotWatcher::new(Budget::default()));
+        });
 
         thread::sleep(Duration::from_millis(10));
-        let client = connect();
-        let client2 = connect();
+        let mut client = connect(8888);
+        assert_eq!(ServerMessage::Hello { player: 1 }, read_message(&mut client));
+        let mut client2 = connect(8888);
+        assert_eq!(ServerMessage::Hello { player: 2 }, read_message(&mut client2));
 
-        // wait for the heartbeat to fire, verify both clients received it
-        thread::sleep(Duration::from_millis(50));
-        verify_heartbeat(&client, &board_bytes);
-        verify_heartbeat(&client2, &board_bytes);
+        // wait for the heartbeat to fire, verify both clients received their own view
+        verify_heartbeat(&mut client, &expected, 1);
+        verify_heartbeat(&mut client2, &expected, 2);
 
         // Should receive a second one
-        thread::sleep(Duration::from_millis(50));
-        verify_heartbeat(&client, &board_bytes);
-        verify_heartbeat(&client2, &board_bytes);
+        verify_heartbeat(&mut client, &expected, 1);
+        verify_heartbeat(&mut client2, &expected, 2);
+
+        // JSON clients get their own ship and can fly it
+        let json_client = connect(8889);
+        let mut lines = BufReader::new(json_client.try_clone().unwrap()).lines();
+        let hello: JsonServerMessage = serde_json::from_str(&lines.next().unwrap().unwrap()).unwrap();
+        assert_eq!(JsonServerMessage::Hello { player: 3 }, hello);
+
+        (&json_client).write_all(b"{\"command\": {\"thrust\": [10, 0, 0]}}\n").unwrap();
+        let moving = lines.take(20).any(|line| {
+            match serde_json::from_str(&line.unwrap()).unwrap() {
+                JsonServerMessage::Snapshot { view, .. } => view.player == 3 && view.velocity[0] > 0.0,
+                _ => false,
+            }
+        });
+        assert!(moving, "json client's commands fly its ship");
+    }
+
+    #[test]
+    fn test_next_player() {
+        let mut connections = HashMap::new();
+        assert_eq!(1, next_player(&connections, &Round::new()));
+        let (tx, _rx) = futures::sync::mpsc::unbounded::<Vec<u8>>();
+        connections.insert("127.0.0.1:1000".parse().unwrap(), Connection {
+            player: 1, format: Format::Bincode, tx: tx.clone(), commands: None
+        });
+        connections.insert("127.0.0.1:1001".parse().unwrap(), Connection {
+            player: 3, format: Format::Json, tx: tx, commands: None
+        });
+        assert_eq!(2, next_player(&connections, &Round::new()));
+
+        let mut round = Round::new();
+        round.add_ship(2, Ship::at_origin());
+        round.add_controller(2, Box::new(RemoteController { commands: Rc::new(RefCell::new(Commands::none())) }));
+        assert_eq!(4, next_player(&connections, &round), "bots and pilots keep their ids");
     }
 
-    fn connect() -> TcpStream {
-        let client = TcpStream::connect("127.0.0.1:8888").unwrap();
+    #[test]
+    fn test_line_codec() {
+        let mut buf = BytesMut::from(&b"{\"fire\": true}\r\n{\"fi"[..]);
+        assert_eq!(Some("{\"fire\": true}".to_string()), LineCodec.decode(&mut buf).unwrap());
+        assert_eq!(None, LineCodec.decode(&mut buf).unwrap(), "waits for the rest of the line");
+
+        let mut long = BytesMut::from(vec![b' '; MAX_LINE_BYTES + 1]);
+        assert!(LineCodec.decode(&mut long).is_err(), "too long even before it ends");
+        long.extend_from_slice(b"\n");
+        assert!(LineCodec.decode(&mut long).is_err());
+    }
+
+    #[test]
+    fn test_remote_controller() {
+        let commands = Rc::new(RefCell::new(Commands::none()));
+        let mut controller = RemoteController { commands: commands.clone() };
+        let view = SensorView::from_board(&{
+            let mut board = Board::new();
+            board.add_ship(1, Ship::at_origin());
+            board
+        }, 1).unwrap();
+
+        let scanner = Scanner::new(Rc::new(Space::new()), &view, 100.0);
+        commands.borrow_mut().fire = true;
+        commands.borrow_mut().thrust.x = 1.0;
+        assert!(controller.tick(&view, &scanner).unwrap().fire);
+        let next = controller.tick(&view, &scanner).unwrap();
+        assert!(!next.fire, "shots aren't repeated");
+        assert_eq!(1.0, next.thrust.x, "thrust is held");
+    }
+
+    fn connect(port: u16) -> TcpStream {
+        let client = TcpStream::connect(("127.0.0.1", port)).unwrap();
         client.set_read_timeout(Some(Duration::from_secs(1))).expect("setting read timeout failed");
         client.set_nodelay(true).expect("disabling nagle's alg failed");
         client
     }
 
-    fn verify_heartbeat(mut client: &TcpStream, expected: &Vec<u8>) {
-        let mut buffer = [0; 512];
-        let bytes_read = match client.read(&mut buffer) {
-            Ok(read) => read,
-            Err(e) => { println!("Got error reading {}", e); 0 }
-        };
-        assert_eq!(bytes_read, expected.len(), "wrong number of heartbeat bytes");
-        let mut read_vec = Vec::with_capacity(bytes_read);
-        read_vec.write_all(&buffer[..bytes_read]).expect("made vector");
-        assert_eq!(&read_vec, expected);
+    fn read_message(client: &mut TcpStream) -> ServerMessage {
+        let mut len_buf = [0; 4];
+        client.read_exact(&mut len_buf).expect("read message length");
+        let mut body = vec![0; LittleEndian::read_u32(&len_buf) as usize];
+        client.read_exact(&mut body).expect("read message body");
+        ServerMessage::from_bytes(&body).expect("decoded message")
+    }
+
+    fn verify_heartbeat(client: &mut TcpStream, expected: &Board, player: PlayerId) {
+        loop {
+            match read_message(client) {
+                // the round ticks between heartbeats, so only the ships are guaranteed to match
+                ServerMessage::Snapshot { view, .. } => {
+                    let mut expected_view = SensorView::from_board(expected, player).unwrap();
+                    expected_view.time = view.time;
+                    assert_eq!(expected_view, view);
+                    return;
+                },
+                ServerMessage::Event(_) | ServerMessage::Checkpoint { .. } => continue,
+                other => panic!("expected a snapshot, got {:?}", other),
+            }
+        }
     }
 }
@@ -0,0 +1,51 @@
+use std::path::Path;
+use std::time::Duration;
+
+use engine::script::{Budget, PilotWatcher, ScriptController};
+use engine::subprocess::{ProcessController, DEFAULT_DEADLINE_MS};
+use engine::wasm::{WasmController, FUEL_PER_TICK};
+use game::board::PlayerId;
+use game::controller::ShipController;
+
+/// Load the pilot at `path` to fly `player`'s ship, picked by the file's extension:
+/// `.wasm` modules and `.rhai` scripts run inside the server, anything else is started as a bot
+/// executable. Scripts are handed to `watcher` to be reloaded when they change, if there is one
+pub fn load(path: &Path, player: PlayerId, watcher: Option<&mut PilotWatcher>)
+            -> Result<Box<ShipController>, String> {
+    let extension = path.extension().and_then(|ext| ext.to_str()).unwrap_or("");
+    let pilot: Box<ShipController> = match (extension, watcher) {
+        ("wasm", _) => Box::new(WasmController::load(path, FUEL_PER_TICK).map_err(|e| e.to_string())?),
+        ("rhai", Some(watcher)) => Box::new(watcher.watch(player, path).map_err(|e| e.to_string())?),
+        ("rhai", None) => Box::new(ScriptController::load(path, &Budget::default()).map_err(|e| e.to_string())?),
+        _ => {
+            let deadline = Duration::from_millis(DEFAULT_DEADLINE_MS);
+            Box::new(ProcessController::spawn(path, deadline).map_err(|e| e.to_string())?)
+        },
+    };
+    Ok(pilot)
+}
+
+#[cfg(test)]
+mod test {
+    use std::env;
+    use std::fs;
+    use super::*;
+
+    #[test]
+    fn test_load() {
+        let dir = env::temp_dir().join("pewpew-test-pilots");
+        fs::create_dir_all(&dir).unwrap();
+        let script = dir.join("pilot.rhai");
+        fs::write(&script, "fn tick() { fire(); }").unwrap();
+        assert!(load(&script, 1, None).is_ok());
+
+        let mut watcher = PilotWatcher::new(Budget::default());
+        assert!(load(&script, 1, Some(&mut watcher)).is_ok());
+        assert!(watcher.poll().is_empty(), "watched from the start");
+
+        fs::write(&script, "fn tick() {").unwrap();
+        assert!(load(&script, 1, None).is_err());
+        assert!(load(&dir.join("missing.wasm"), 1, None).is_err());
+        assert!(load(&dir.join("missing-bot"), 1, None).is_err());
+    }
+}
@@ -0,0 +1,107 @@
+use bincode::{serialize, deserialize, Infinite};
+
+use engine::lifecycle::Phase;
+use engine::networking::len_encode_bytes;
+use game::board::{PlayerId, TeamId, Timestep};
+use game::controller::{Commands, SensorView};
+use game::event::Event;
+use game::mode::Results;
+
+/// Everything the server sends to a connected client
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+pub enum ServerMessage {
+    /// First message on every connection, tells the client which ship it owns
+    Hello { player: PlayerId },
+    /// The board as seen by the client's ship, and where the match is at
+    Snapshot { phase: Phase, view: SensorView },
+    /// Where the match is at, for clients that don't have a ship to see it from
+    Phase(Phase),
+    Event(Event),
+    /// Sent once when the match ends
+    Results(Results),
+    /// The round's state hash at `time`, every `CHECKPOINT_INTERVAL_MS` while the round is live
+    /// Only a client with the whole round to hand (a spectator running its own copy, or a replay
+    /// tool relaying one) can check it, a ship's fogged view isn't enough to recompute it
+    Checkpoint { time: Timestep, hash: u64 },
+}
+
+impl ServerMessage {
+    /// Serialize and length-prefix the message, ready to be written to a socket
+    pub fn to_frame(&self) -> Vec<u8> {
+        let bytes = serialize(self, Infinite).expect("Error serializing server message");
+        len_encode_bytes(bytes)
+    }
+
+    /// Decode the body of a frame (without its length prefix)
+    pub fn from_bytes(bytes: &[u8]) -> Option<ServerMessage> {
+        deserialize(bytes).ok()
+    }
+}
+
+/// Everything a client can send to the server
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+pub enum ClientMessage {
+    /// Replace the commands flying the client's ship
+    Command(Commands),
+    /// Switch the client's ship to another team, only honored in the lobby
+    ChooseTeam(TeamId),
+}
+
+impl ClientMessage {
+    pub fn to_frame(&self) -> Vec<u8> {
+        let bytes = serialize(self, Infinite).expect("Error serializing client message");
+        len_encode_bytes(bytes)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Option<ClientMessage> {
+        deserialize(bytes).ok()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use game::board::Board;
+    use game::ship::Ship;
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let mut board = Board::new();
+        board.add_ship(3, Ship::at_origin());
+        let messages = vec![
+            ServerMessage::Hello { player: 3 },
+            ServerMessage::Snapshot {
+                phase: Phase::Countdown { remaining_ms: 1200 },
+                view: SensorView::from_board(&board, 3).unwrap(),
+            },
+            ServerMessage::Phase(Phase::Results { remaining_ms: 300 }),
+            ServerMessage::Event(Event::Destroyed { player: 3, by: None }),
+            ServerMessage::Results(Results {
+                winners: vec![3],
+                winning_teams: vec![],
+                scores: vec![(3, 2), (1, 0)],
+                time: 1500,
+                seed: 9,
+            }),
+            ServerMessage::Checkpoint { time: 1000, hash: 0xdead_beef_0000_0001 },
+        ];
+        for message in messages {
+            let frame = message.to_frame();
+            let decoded = ServerMessage::from_bytes(&frame[4..]).expect("decoded message");
+            assert_eq!(message, decoded);
+        }
+    }
+
+    #[test]
+    fn client_round_trip() {
+        for message in vec![ClientMessage::Command(Commands::none()), ClientMessage::ChooseTeam(2)] {
+            let frame = message.to_frame();
+            assert_eq!(Some(message), ClientMessage::from_bytes(&frame[4..]));
+        }
+    }
+
+    #[test]
+    fn garbage() {
+        assert!(ServerMessage::from_bytes(&[255, 255, 255, 255, 1]).is_none());
+    }
+}
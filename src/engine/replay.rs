@@ -0,0 +1,414 @@
+use std::fmt;
+use std::fs::File;
+use std::io;
+use std::io::{Read, Write};
+use std::path::Path;
+
+use bincode::{serialize, deserialize, Infinite};
+use bytes::{ByteOrder, LittleEndian};
+
+use engine::desync::{Checkpoint, Desync};
+use engine::engine::{Round, TICKS_TO_MS};
+use engine::save::SavedRound;
+use game::board::{PlayerId, TeamId};
+use game::controller::{Commands, Fault, SensorView};
+use game::event::Event;
+use game::ship::Ship;
+
+/// Bumped whenever the replay format changes, old replays won't play the same way
+const REPLAY_VERSION: u32 = 4;
+
+#[derive(Debug)]
+pub enum ReplayError {
+    Io(io::Error),
+    Decode(String),
+    /// Recorded by a different version of the server
+    Version(u32),
+}
+
+impl fmt::Display for ReplayError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ReplayError::Io(ref e) => write!(f, "couldn't read replay: {}", e),
+            ReplayError::Decode(ref e) => write!(f, "couldn't decode replay: {}", e),
+            ReplayError::Version(version) => {
+                write!(f, "replay is version {}, only version {} can be played", version, REPLAY_VERSION)
+            },
+        }
+    }
+}
+
+/// Everything that went into one tick of a recorded round
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct Frame {
+    /// Ships that joined since the last tick, and the team they joined
+    pub joined: Vec<(PlayerId, Option<TeamId>, Ship)>,
+    /// What each controller came up with, in the order they were applied
+    pub commands: Vec<(PlayerId, Result<Commands, Fault>)>,
+    /// How the round looked after the tick, every `CHECKPOINT_TICKS` ticks
+    pub check: Option<Checkpoint>,
+}
+
+/// A recorded round, enough to play it over again exactly as it happened
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct Replay {
+    /// How the round looked when recording started
+    pub start: SavedRound,
+    pub frames: Vec<Frame>,
+}
+
+impl Replay {
+    pub fn new(start: SavedRound) -> Replay {
+        Replay {
+            start: start,
+            frames: Vec::new(),
+        }
+    }
+
+    /// Seed the recorded round was played with
+    pub fn seed(&self) -> u32 {
+        self.start.rng.seed()
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = vec![0; 4];
+        LittleEndian::write_u32(&mut bytes, REPLAY_VERSION);
+        bytes.append(&mut serialize(self, Infinite).expect("Error serializing replay"));
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Replay, ReplayError> {
+        if bytes.len() < 4 {
+            return Err(ReplayError::Decode("too short".to_string()));
+        }
+        let version = LittleEndian::read_u32(&bytes[..4]);
+        if version != REPLAY_VERSION {
+            return Err(ReplayError::Version(version));
+        }
+        deserialize(&bytes[4..]).map_err(|e| ReplayError::Decode(e.to_string()))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), ReplayError> {
+        File::create(path)
+            .and_then(|mut file| file.write_all(&self.to_bytes()))
+            .map_err(ReplayError::Io)
+    }
+
+    pub fn load(path: &Path) -> Result<Replay, ReplayError> {
+        let mut bytes = Vec::new();
+        File::open(path)
+            .and_then(|mut file| file.read_to_end(&mut bytes))
+            .map_err(ReplayError::Io)?;
+        Replay::from_bytes(&bytes)
+    }
+}
+
+/// Plays a replay back by running the round again with the recorded commands
+/// Seeking backwards starts over from the beginning, the simulation only runs one way
+/// The re-run is checked against the replay's checkpoints as it goes
+pub struct Playback {
+    replay: Replay,
+    round: Round,
+    /// The first checkpoint the re-run didn't match
+    desync: Option<Desync>,
+    /// Checkpoints played past since the last `drain_checkpoints`, without their boards
+    passed: Vec<Checkpoint>,
+    /// Frames played so far
+    position: usize,
+    paused: bool,
+    /// Playback rate, 1.0 is as fast as the round was played
+    speed: f32,
+    /// Playback time that hasn't added up to a whole tick yet
+    pending_ms: f32,
+}
+
+impl Playback {
+    pub fn new(replay: Replay) -> Playback {
+        let round = Round::restore(replay.start.clone());
+        Playback {
+            replay: replay,
+            round: round,
+            desync: None,
+            passed: Vec::new(),
+            position: 0,
+            paused: false,
+            speed: 1.0,
+            pending_ms: 0.0,
+        }
+    }
+
+    /// The round as it is at the current tick
+    pub fn round(&self) -> &Round {
+        &self.round
+    }
+
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
+    /// How many ticks were recorded
+    pub fn len(&self) -> usize {
+        self.replay.frames.len()
+    }
+
+    pub fn finished(&self) -> bool {
+        self.position >= self.len()
+    }
+
+    pub fn pause(&mut self) {
+        self.paused = true;
+    }
+
+    pub fn resume(&mut self) {
+        self.paused = false;
+    }
+
+    pub fn paused(&self) -> bool {
+        self.paused
+    }
+
+    pub fn speed(&self) -> f32 {
+        self.speed
+    }
+
+    /// Change the playback rate, e.g. 2.0 for double speed, never below zero
+    pub fn set_speed(&mut self, speed: f32) {
+        self.speed = speed.max(0.0);
+    }
+
+    /// Play on by however much of the recording `ms` of real time covers at the current speed
+    /// Nothing moves while paused, returns the number of ticks played
+    pub fn advance(&mut self, ms: u32) -> usize {
+        if self.paused {
+            return 0;
+        }
+        self.pending_ms += ms as f32 * self.speed;
+        let ticks = (self.pending_ms / TICKS_TO_MS as f32) as usize;
+        self.pending_ms -= (ticks as u32 * TICKS_TO_MS) as f32;
+        self.step(ticks)
+    }
+
+    /// Play the next `ticks` recorded ticks, paused or not, returns how many there were
+    pub fn step(&mut self, ticks: usize) -> usize {
+        let end = (self.position + ticks).min(self.len());
+        for frame in &self.replay.frames[self.position..end] {
+            self.round.play_frame(frame);
+            if self.desync.is_none() {
+                if let Some(ref check) = frame.check {
+                    self.desync = check.verify(self.round.state_hash(), &self.round.board);
+                }
+            }
+            if let Some(ref check) = frame.check {
+                self.passed.push(Checkpoint { time: check.time, hash: check.hash, board: None });
+            }
+        }
+        let played = end - self.position;
+        self.position = end;
+        played
+    }
+
+    /// Jump to `tick`, events from before it are thrown away
+    pub fn seek(&mut self, tick: usize) {
+        if tick < self.position {
+            self.round = Round::restore(self.replay.start.clone());
+            self.desync = None;
+            self.position = 0;
+        }
+        let ahead = tick - self.position;
+        self.step(ahead);
+        self.round.drain_events();
+        self.passed.clear();
+        self.pending_ms = 0.0;
+    }
+
+    /// Where the re-run first stopped matching the recording, if it has
+    pub fn desync(&self) -> Option<&Desync> {
+        self.desync.as_ref()
+    }
+
+    /// The board as `player`'s sensors saw it at the current tick
+    pub fn view(&self, player: PlayerId) -> Option<SensorView> {
+        self.round.sensor_view(player)
+    }
+
+    /// Take all of the events that have been played back since the last call
+    pub fn drain_events(&mut self) -> Vec<Event> {
+        self.round.drain_events()
+    }
+
+    /// Take the recorded checkpoints played past since the last call, to pass on to clients
+    pub fn drain_checkpoints(&mut self) -> Vec<Checkpoint> {
+        self.passed.drain(..).collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use na::Vector3;
+    use engine::bots::{Bot, Tactic};
+    use game::controller::{Autopilot, ShipController};
+    use game::mode::{GameMode, Limits};
+    use game::obstacle::Obstacle;
+    use game::rng::GameRng;
+    use game::scanner::Scanner;
+    use super::*;
+
+    /// Holds wherever its ship was when it first got the controls
+    struct Holding;
+
+    impl ShipController for Holding {
+        fn tick(&mut self, _view: &SensorView, _scanner: &Scanner) -> Result<Commands, Fault> {
+            let mut commands = Commands::none();
+            commands.autopilot = Some(Autopilot::Hold);
+            Ok(commands)
+        }
+    }
+
+    /// Two bots going at each other, recorded from the start
+    fn recorded(ticks: u32) -> (Round, Replay) {
+        let mut round = Round::new();
+        round.add_ship(1, Ship::at(Vector3::new(-20.0, 0.0, 0.0)));
+        round.add_ship(2, Ship::at(Vector3::new(20.0, 5.0, 0.0)));
+        round.set_mode(GameMode::Deathmatch, Limits::none());
+        round.reseed(5);
+        round.add_controller(1, Box::new(Bot::new(Tactic::Seeker, GameRng::new(1))));
+        round.add_controller(2, Box::new(Bot::new(Tactic::Kiter, GameRng::new(2))));
+        round.record();
+        round.fast_forward(ticks);
+        let replay = round.take_recording().expect("recording");
+        (round, replay)
+    }
+
+    #[test]
+    fn test_plays_back() {
+        let (mut round, replay) = recorded(300);
+        assert_eq!(300, replay.frames.len());
+        assert_eq!(5, replay.seed());
+
+        let mut playback = Playback::new(replay);
+        assert_eq!(300, playback.step(1000));
+        assert!(playback.finished());
+        assert_eq!(round.board, playback.round().board, "ends up exactly where the round did");
+        assert_eq!(round.drain_events(), playback.drain_events());
+        assert_eq!(round.scoreboard.standings(), playback.round().scoreboard.standings());
+        assert_eq!(None, playback.desync());
+    }
+
+    #[test]
+    fn test_desync() {
+        let (_, mut replay) = recorded(100);
+        assert_eq!(10, replay.frames.iter().filter(|frame| frame.check.is_some()).count());
+        // pretend the recorded round had ship 2 a little further along halfway through
+        let board = {
+            let check = replay.frames[49].check.as_mut().expect("checkpoint at 500ms");
+            check.hash ^= 1;
+            check.board.as_mut().unwrap()
+        };
+        let ship = board.ships.get(&2).unwrap().clone();
+        board.ships.insert(2, Ship::at(ship.position() + Vector3::new(1.0, 0.0, 0.0)));
+
+        let mut playback = Playback::new(replay);
+        playback.step(100);
+        let desync = playback.desync().expect("desync").clone();
+        assert_eq!(500, desync.time, "the first checkpoint that doesn't match");
+        assert!(desync.diff.iter().any(|line| line.starts_with("ship 2 position")), "{}", desync);
+        assert!(desync.diff.iter().all(|line| !line.starts_with("ship 1")));
+
+        playback.seek(20);
+        assert_eq!(None, playback.desync(), "starting over forgets it");
+    }
+
+    #[test]
+    fn test_joined_mid_round() {
+        let mut round = Round::new();
+        round.add_ship(1, Ship::at(Vector3::new(-20.0, 0.0, 0.0)));
+        round.add_controller(1, Box::new(Bot::new(Tactic::Seeker, GameRng::new(1))));
+        round.record();
+        round.fast_forward(50);
+        round.add_ship(2, Ship::at(Vector3::new(20.0, 0.0, 0.0)));
+        round.add_controller(2, Box::new(Bot::new(Tactic::Dodger, GameRng::new(2))));
+        round.fast_forward(50);
+
+        let mut playback = Playback::new(round.take_recording().unwrap());
+        playback.step(100);
+        assert_eq!(round.board, playback.round().board);
+    }
+
+    #[test]
+    fn test_recorded_mid_round() {
+        let mut round = Round::new();
+        round.add_obstacle(Obstacle::Asteroid { position: Vector3::new(0.0, 15.0, 0.0), radius: 3.0 });
+        round.add_ship(1, Ship::at(Vector3::new(-20.0, 0.0, 0.0)));
+        round.add_ship(2, Ship::at(Vector3::new(20.0, 5.0, 0.0)));
+        let mut drifting = Ship::at(Vector3::new(0.0, -20.0, 0.0));
+        let orientation = drifting.orientation();
+        drifting.update(drifting.position(), orientation, Vector3::new(0.0, 0.0, 5.0), Vector3::new(0.0, 0.0, 0.0));
+        round.add_ship(3, drifting);
+        round.add_controller(1, Box::new(Bot::new(Tactic::Seeker, GameRng::new(1))));
+        round.add_controller(2, Box::new(Bot::new(Tactic::Kiter, GameRng::new(2))));
+        round.add_controller(3, Box::new(Holding));
+        round.fast_forward(120);
+        round.record();
+        round.fast_forward(200);
+        let replay = round.take_recording().unwrap();
+        assert_eq!(1, replay.start.obstacles.len(), "scenery from outside the map");
+
+        let mut playback = Playback::new(replay);
+        playback.step(200);
+        assert_eq!(round.board, playback.round().board, "weapons cooling down and ships holding carry over");
+        assert_eq!(None, playback.desync());
+    }
+
+    #[test]
+    fn test_seek() {
+        let (_, replay) = recorded(200);
+        let mut straight = Playback::new(replay.clone());
+        straight.step(120);
+
+        let mut seeking = Playback::new(replay);
+        seeking.seek(180);
+        seeking.seek(120);
+        assert_eq!(120, seeking.position());
+        assert_eq!(straight.round().board, seeking.round().board, "back to the same state");
+        assert!(seeking.drain_events().is_empty(), "skipped events aren't played");
+        assert!(seeking.drain_checkpoints().is_empty(), "nor are skipped checkpoints");
+        seeking.step(20);
+        let times: Vec<u32> = seeking.drain_checkpoints().iter().map(|check| check.time).collect();
+        assert_eq!(vec![1300, 1400], times);
+        assert_eq!(straight.view(1), seeking.view(1));
+    }
+
+    #[test]
+    fn test_pause_and_speed() {
+        let (_, replay) = recorded(100);
+        let mut playback = Playback::new(replay);
+        assert_eq!(5, playback.advance(55));
+        assert_eq!(1, playback.advance(5), "leftover time carries over");
+
+        playback.pause();
+        assert_eq!(0, playback.advance(1000));
+        assert_eq!(2, playback.step(2), "can still step while paused");
+
+        playback.resume();
+        playback.set_speed(2.0);
+        assert_eq!(10, playback.advance(50));
+        playback.set_speed(-1.0);
+        assert_eq!(0.0, playback.speed());
+        assert_eq!(0, playback.advance(1000));
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let (_, replay) = recorded(50);
+        let bytes = replay.to_bytes();
+        assert_eq!(replay, Replay::from_bytes(&bytes).expect("decoded"));
+
+        let mut old = bytes.clone();
+        old[0] = 0;
+        match Replay::from_bytes(&old) {
+            Err(ReplayError::Version(0)) => (),
+            other => panic!("expected a version error, got {:?}", other.map(|_| ())),
+        }
+        assert!(Replay::from_bytes(&bytes[..10]).is_err());
+    }
+}
@@ -0,0 +1,88 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use bincode::{serialize, deserialize, Infinite};
+use bytes::{ByteOrder, LittleEndian};
+use na::{Isometry3, Vector3};
+
+use game::board::{Board, PlayerId};
+use game::controller::FaultRecord;
+use game::boundary::Boundary;
+use game::event::Event;
+use game::map::Map;
+use game::mode::{FriendlyFire, Scoreboard};
+use game::obstacle::Obstacle;
+use game::rng::GameRng;
+use game::sensors::SensorModel;
+
+/// Bumped whenever the save format changes
+const SAVE_VERSION: u32 = 2;
+
+#[derive(Debug)]
+pub enum SaveError {
+    Decode(String),
+    /// Saved by a different version of the server
+    Version(u32),
+}
+
+impl fmt::Display for SaveError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            SaveError::Decode(ref e) => write!(f, "couldn't decode saved round: {}", e),
+            SaveError::Version(version) => {
+                write!(f, "round was saved by version {}, only version {} can be loaded", version, SAVE_VERSION)
+            },
+        }
+    }
+}
+
+/// Everything about a ship's physics body that carries over from one step to the next
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct BodyState {
+    pub position: Isometry3<f32>,
+    pub lin_vel: Vector3<f32>,
+    pub ang_vel: Vector3<f32>,
+    /// Whether the body is awake, asleep bodies don't move until something wakes them
+    pub active: bool,
+    pub deactivation_threshold: Option<f32>,
+}
+
+/// A round frozen between two ticks, see `Round::save` and `Round::load`
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct SavedRound {
+    pub board: Board,
+    pub bodies: Vec<(PlayerId, BodyState)>,
+    /// Every obstacle in the world, the map's included
+    pub obstacles: Vec<Obstacle>,
+    pub map: Map,
+    pub boundary: Boundary,
+    pub sensors: SensorModel,
+    pub cooldowns: HashMap<PlayerId, u32>,
+    pub faults: HashMap<PlayerId, FaultRecord>,
+    pub anchors: HashMap<PlayerId, Vector3<f32>>,
+    /// Events that hadn't been drained yet
+    pub events: Vec<Event>,
+    pub scoreboard: Scoreboard,
+    pub friendly_fire: FriendlyFire,
+    pub rng: GameRng,
+}
+
+impl SavedRound {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = vec![0; 4];
+        LittleEndian::write_u32(&mut bytes, SAVE_VERSION);
+        bytes.append(&mut serialize(self, Infinite).expect("Error serializing round"));
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<SavedRound, SaveError> {
+        if bytes.len() < 4 {
+            return Err(SaveError::Decode("too short".to_string()));
+        }
+        let version = LittleEndian::read_u32(&bytes[..4]);
+        if version != SAVE_VERSION {
+            return Err(SaveError::Version(version));
+        }
+        deserialize(&bytes[4..]).map_err(|e| SaveError::Decode(e.to_string()))
+    }
+}
@@ -0,0 +1,460 @@
+use std::cell::RefCell;
+use std::fmt;
+use std::fs::File;
+use std::io;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::str;
+
+use na::Vector3;
+use rhai::{Array, Dynamic, Engine, EvalAltResult, Map, Scope, AST, FLOAT, INT};
+
+use game::board::{PlayerId, TeamId};
+use game::controller::{Commands, Contact, Fault, SensorView, ShipController};
+use game::scanner::{RayHit, Scanner};
+
+#[derive(Debug)]
+pub enum ScriptError {
+    Io(io::Error),
+    Compile(String),
+}
+
+impl fmt::Display for ScriptError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ScriptError::Io(ref e) => write!(f, "couldn't read script: {}", e),
+            ScriptError::Compile(ref e) => write!(f, "couldn't compile script: {}", e),
+        }
+    }
+}
+
+/// Hard limits on what a script may do in a single tick
+#[derive(Clone, Debug)]
+pub struct Budget {
+    pub max_operations: u64,
+    pub max_call_depth: usize,
+    pub max_string_size: usize,
+    pub max_array_size: usize,
+    pub max_map_size: usize,
+}
+
+impl Default for Budget {
+    fn default() -> Budget {
+        Budget {
+            max_operations: 10_000,
+            max_call_depth: 16,
+            max_string_size: 1024,
+            max_array_size: 256,
+            max_map_size: 256,
+        }
+    }
+}
+
+/// A pilot whose per-tick logic lives in a Rhai script
+///
+/// The script must define a `tick()` function. It can read its own ship through `position()`,
+/// `velocity()`, `heading()`, `hull()`, `team()` and `time()`, see other ships through
+/// `contacts()`, and fly with `thrust(v)`, `torque(v)` and `fire()`. Vectors are built with `vec3(x, y, z)`.
+/// `nearest()`, `ships_within(radius)` and `raycast(dir, max_dist)` search the space around the
+/// ship for things its sensors can see, and return `()` when there's nothing there. A ray's hit
+/// is a map like `#{distance: 9.5, kind: "ship", player: 2}`, `kind` is `"obstacle"` for scenery.
+/// Teams are numbered from 1, -1 means no team.
+pub struct ScriptController {
+    engine: Engine,
+    ast: AST,
+    path: Option<PathBuf>,
+    view: Rc<RefCell<Option<SensorView>>>,
+    scanner: Rc<RefCell<Option<Scanner>>>,
+    commands: Rc<RefCell<Commands>>,
+}
+
+impl ScriptController {
+    pub fn load(path: &Path, budget: &Budget) -> Result<ScriptController, ScriptError> {
+        let source = read(path).map_err(ScriptError::Io)?;
+        ScriptController::from_file_contents(path, &source, budget)
+    }
+
+    /// Compile `source`, already read from `path`, so the file is only read the once
+    fn from_file_contents(path: &Path, source: &[u8], budget: &Budget) -> Result<ScriptController, ScriptError> {
+        let source = str::from_utf8(source)
+            .map_err(|e| ScriptError::Io(io::Error::new(io::ErrorKind::InvalidData, e)))?;
+        let mut controller = ScriptController::from_source(source, budget)?;
+        controller.path = Some(path.to_path_buf());
+        Ok(controller)
+    }
+
+    pub fn from_source(source: &str, budget: &Budget) -> Result<ScriptController, ScriptError> {
+        let view = Rc::new(RefCell::new(None));
+        let scanner = Rc::new(RefCell::new(None));
+        let commands = Rc::new(RefCell::new(Commands::none()));
+        let engine = pilot_engine(view.clone(), scanner.clone(), commands.clone(), budget);
+        let ast = engine.compile(source).map_err(|e| ScriptError::Compile(e.to_string()))?;
+        Ok(ScriptController {
+            engine: engine,
+            ast: ast,
+            path: None,
+            view: view,
+            scanner: scanner,
+            commands: commands,
+        })
+    }
+
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_ref().map(|p| p.as_path())
+    }
+}
+
+impl ShipController for ScriptController {
+    fn tick(&mut self, view: &SensorView, scanner: &Scanner) -> Result<Commands, Fault> {
+        *self.view.borrow_mut() = Some(view.clone());
+        *self.scanner.borrow_mut() = Some(scanner.clone());
+        *self.commands.borrow_mut() = Commands::none();
+
+        let mut scope = Scope::new();
+        let result = self.engine.call_fn::<Dynamic>(&mut scope, &self.ast, "tick", ());
+        *self.scanner.borrow_mut() = None; // don't hang on to the world between ticks
+        result.map_err(to_fault)?;
+        Ok(self.commands.borrow().clone())
+    }
+}
+
+struct WatchedPilot {
+    player: PlayerId,
+    path: PathBuf,
+    /// What the file held when it was last loaded, timestamps are too coarse to go by
+    source: Option<Vec<u8>>,
+}
+
+/// Keeps track of the pilot scripts loaded for each player and reloads them when they change
+pub struct PilotWatcher {
+    budget: Budget,
+    pilots: Vec<WatchedPilot>,
+}
+
+impl PilotWatcher {
+    pub fn new(budget: Budget) -> PilotWatcher {
+        PilotWatcher {
+            budget: budget,
+            pilots: Vec::new(),
+        }
+    }
+
+    /// Load the pilot at `path` for `player` and watch the file for changes
+    pub fn watch(&mut self, player: PlayerId, path: &Path) -> Result<ScriptController, ScriptError> {
+        let source = read(path).map_err(ScriptError::Io)?;
+        let pilot = ScriptController::from_file_contents(path, &source, &self.budget)?;
+        self.pilots.push(WatchedPilot {
+            player: player,
+            path: path.to_path_buf(),
+            source: Some(source),
+        });
+        Ok(pilot)
+    }
+
+    /// Recompile every script whose file changed since it was last loaded
+    /// A failed compile is only reported once per change to the file
+    pub fn poll(&mut self) -> Vec<(PlayerId, Result<ScriptController, ScriptError>)> {
+        let mut reloaded = Vec::new();
+        for pilot in self.pilots.iter_mut() {
+            let source = match read(&pilot.path) {
+                Ok(ref source) if Some(source) == pilot.source.as_ref() => continue,
+                Ok(source) => source,
+                Err(_) => continue,
+            };
+            let compiled = ScriptController::from_file_contents(&pilot.path, &source, &self.budget);
+            pilot.source = Some(source);
+            reloaded.push((pilot.player, compiled));
+        }
+        reloaded
+    }
+}
+
+fn read(path: &Path) -> io::Result<Vec<u8>> {
+    let mut bytes = Vec::new();
+    File::open(path).and_then(|mut f| f.read_to_end(&mut bytes)).map(|_| bytes)
+}
+
+fn to_fault(error: Box<EvalAltResult>) -> Fault {
+    match *error {
+        EvalAltResult::ErrorTooManyOperations(..) |
+        EvalAltResult::ErrorDataTooLarge(..) |
+        EvalAltResult::ErrorStackOverflow(..) => Fault::OverBudget(error.to_string()),
+        _ => Fault::Error(error.to_string()),
+    }
+}
+
+/// Build a sandboxed engine whose ship API reads `view` and writes `commands`
+fn pilot_engine(view: Rc<RefCell<Option<SensorView>>>,
+                scanner: Rc<RefCell<Option<Scanner>>>,
+                commands: Rc<RefCell<Commands>>,
+                budget: &Budget) -> Engine {
+    let mut engine = Engine::new();
+    engine.disable_symbol("eval");
+    engine.set_max_operations(budget.max_operations)
+        .set_max_call_levels(budget.max_call_depth)
+        .set_max_string_size(budget.max_string_size)
+        .set_max_array_size(budget.max_array_size)
+        .set_max_map_size(budget.max_map_size);
+    register_vectors(&mut engine);
+
+    engine.register_type_with_name::<Contact>("Contact")
+        .register_get("player", |c: &mut Contact| c.player as INT)
+        .register_get("position", |c: &mut Contact| c.position)
+        .register_get("velocity", |c: &mut Contact| c.velocity)
+        .register_get("team", |c: &mut Contact| team_number(c.team));
+
+    let v = view.clone();
+    engine.register_fn("position", move || read(&v, |view| view.position));
+    let v = view.clone();
+    engine.register_fn("velocity", move || read(&v, |view| view.velocity));
+    let v = view.clone();
+    engine.register_fn("heading", move || read(&v, |view| view.heading()));
+    let v = view.clone();
+    engine.register_fn("hull", move || read(&v, |view| view.hull as FLOAT));
+    let v = view.clone();
+    engine.register_fn("team", move || read(&v, |view| team_number(view.team)));
+    let v = view.clone();
+    engine.register_fn("time", move || read(&v, |view| view.time as INT));
+    let v = view.clone();
+    engine.register_fn("contacts", move || {
+        read(&v, |view| {
+            view.contacts.iter().cloned().map(Dynamic::from).collect::<Array>()
+        })
+    });
+
+    let s = scanner.clone();
+    engine.register_fn("nearest", move || {
+        scan(&s, |scanner| {
+            scanner.nearest().map_or(Dynamic::UNIT, |(player, _)| Dynamic::from(player as INT))
+        })
+    });
+    let s = scanner.clone();
+    engine.register_fn("ships_within", move |radius: FLOAT| {
+        scan(&s, |scanner| {
+            scanner.ships_within(radius as f32).into_iter()
+                .map(|(player, _)| Dynamic::from(player as INT))
+                .collect::<Array>()
+        })
+    });
+    let s = scanner.clone();
+    engine.register_fn("raycast", move |dir: Vector3<f32>, max_dist: FLOAT| {
+        scan(&s, |scanner| {
+            scanner.raycast(dir, max_dist as f32).map_or(Dynamic::UNIT, |(hit, dist)| {
+                let mut found = Map::new();
+                found.insert("distance".into(), Dynamic::from(dist as FLOAT));
+                match hit {
+                    RayHit::Ship(player) => {
+                        found.insert("kind".into(), Dynamic::from("ship"));
+                        found.insert("player".into(), Dynamic::from(player as INT));
+                    },
+                    RayHit::Obstacle => {
+                        found.insert("kind".into(), Dynamic::from("obstacle"));
+                    },
+                }
+                Dynamic::from(found)
+            })
+        })
+    });
+
+    let c = commands.clone();
+    engine.register_fn("thrust", move |thrust: Vector3<f32>| c.borrow_mut().thrust = thrust);
+    let c = commands.clone();
+    engine.register_fn("torque", move |torque: Vector3<f32>| c.borrow_mut().torque = torque);
+    let c = commands.clone();
+    engine.register_fn("fire", move || c.borrow_mut().fire = true);
+
+    engine
+}
+
+fn read<T, F>(view: &Rc<RefCell<Option<SensorView>>>, f: F) -> T
+    where F: Fn(&SensorView) -> T
+{
+    f(view.borrow().as_ref().expect("script called outside of a tick"))
+}
+
+fn scan<T, F>(scanner: &Rc<RefCell<Option<Scanner>>>, f: F) -> T
+    where F: Fn(&Scanner) -> T
+{
+    f(scanner.borrow().as_ref().expect("script called outside of a tick"))
+}
+
+fn team_number(team: Option<TeamId>) -> INT {
+    team.map_or(-1, |team| team as INT)
+}
+
+/// Expose `na::Vector3` to scripts as `Vec3`
+fn register_vectors(engine: &mut Engine) {
+    engine.register_type_with_name::<Vector3<f32>>("Vec3")
+        .register_fn("vec3", |x: FLOAT, y: FLOAT, z: FLOAT| Vector3::new(x as f32, y as f32, z as f32))
+        .register_get("x", |v: &mut Vector3<f32>| v.x as FLOAT)
+        .register_get("y", |v: &mut Vector3<f32>| v.y as FLOAT)
+        .register_get("z", |v: &mut Vector3<f32>| v.z as FLOAT)
+        .register_fn("+", |a: Vector3<f32>, b: Vector3<f32>| a + b)
+        .register_fn("-", |a: Vector3<f32>, b: Vector3<f32>| a - b)
+        .register_fn("-", |a: Vector3<f32>| -a)
+        .register_fn("*", |a: Vector3<f32>, s: FLOAT| a * s as f32)
+        .register_fn("*", |s: FLOAT, a: Vector3<f32>| a * s as f32)
+        .register_fn("dot", |a: Vector3<f32>, b: Vector3<f32>| a.dot(&b) as FLOAT)
+        .register_fn("cross", |a: Vector3<f32>, b: Vector3<f32>| a.cross(&b))
+        .register_fn("norm", |a: Vector3<f32>| a.norm() as FLOAT)
+        .register_fn("normalize", |a: Vector3<f32>| {
+            if a.norm() > 0.0 { a.normalize() } else { a }
+        })
+        .register_fn("to_string", |a: &mut Vector3<f32>| format!("({}, {}, {})", a.x, a.y, a.z));
+}
+
+#[cfg(test)]
+mod test {
+    use std::env;
+    use std::fs::File;
+    use std::io::Write;
+    use na::Vector3;
+    use engine::engine::Round;
+    use engine::spatial::Space;
+    use game::board::Board;
+    use game::ship::Ship;
+    use super::*;
+
+    fn view() -> SensorView {
+        let mut board = Board::new();
+        board.add_ship(1, Ship::at_origin());
+        board.add_ship(2, Ship::at(Vector3::new(0.0, 4.0, 0.0)));
+        SensorView::from_board(&board, 1).unwrap()
+    }
+
+    fn scanner() -> Scanner {
+        Scanner::new(Rc::new(Space::new()), &view(), 100.0)
+    }
+
+    #[test]
+    fn test_compile_error() {
+        match ScriptController::from_source("fn tick() { thrust(", &Budget::default()) {
+            Err(ScriptError::Compile(_)) => {},
+            other => panic!("expected compile error, got {:?}", other.err()),
+        }
+    }
+
+    #[test]
+    fn test_commands() {
+        let mut pilot = ScriptController::from_source(r#"
+            fn tick() {
+                let target = contacts()[0];
+                thrust(normalize(target.position - position()) * 2.0);
+                torque(vec3(0.0, 0.0, 1.0));
+                if target.player == 2 { fire(); }
+            }
+        "#, &Budget::default()).expect("script compiles");
+
+        let commands = pilot.tick(&view(), &scanner()).expect("no fault");
+        assert_eq!(Vector3::new(0.0, 2.0, 0.0), commands.thrust);
+        assert_eq!(Vector3::new(0.0, 0.0, 1.0), commands.torque);
+        assert!(commands.fire);
+    }
+
+    #[test]
+    fn test_commands_reset_each_tick() {
+        let mut pilot = ScriptController::from_source(r#"
+            fn tick() { if time() == 0 { fire(); } }
+        "#, &Budget::default()).expect("script compiles");
+        let mut view = view();
+        assert!(pilot.tick(&view, &scanner()).unwrap().fire);
+        view.time = 10;
+        assert!(!pilot.tick(&view, &scanner()).unwrap().fire);
+    }
+
+    #[test]
+    fn test_spatial_queries() {
+        let mut round = Round::new();
+        round.add_ship(1, Ship::at_origin());
+        round.add_ship(2, Ship::at(Vector3::new(10.0, 0.0, 0.0)));
+        round.add_ship(3, Ship::at(Vector3::new(-20.0, 0.0, 0.0)));
+        round.fast_forward(1);
+
+        let mut pilot = ScriptController::from_source(r#"
+            fn tick() {
+                let near = ships_within(15.0);
+                let hit = raycast(vec3(1.0, 0.0, 0.0), 50.0);
+                if nearest() == 2 && near.len() == 1 && raycast(vec3(0.0, 1.0, 0.0), 50.0) == ()
+                    && hit.kind == "ship" && hit.player == 2 {
+                    fire();
+                }
+            }
+        "#, &Budget::default()).expect("script compiles");
+        let view = round.sensor_view(1).expect("ship 1 is on the board");
+        assert!(pilot.tick(&view, &round.scanner(&view)).unwrap().fire);
+    }
+
+    #[test]
+    fn test_runtime_error() {
+        let mut pilot = ScriptController::from_source("fn tick() { contacts()[5].player }",
+                                                      &Budget::default())
+            .expect("script compiles");
+        match pilot.tick(&view(), &scanner()) {
+            Err(Fault::Error(_)) => {},
+            other => panic!("expected runtime error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_infinite_loop() {
+        let mut pilot = ScriptController::from_source("fn tick() { loop { fire(); } }",
+                                                      &Budget::default())
+            .expect("script compiles");
+        match pilot.tick(&view(), &scanner()) {
+            Err(Fault::OverBudget(_)) => {},
+            other => panic!("expected budget overrun, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_memory_hog() {
+        let mut pilot = ScriptController::from_source(r#"
+            fn tick() {
+                let hoard = [];
+                for i in 0..10000 { hoard.push(i); }
+            }
+        "#, &Budget { max_operations: 1_000_000, ..Budget::default() }).expect("script compiles");
+        match pilot.tick(&view(), &scanner()) {
+            Err(Fault::OverBudget(_)) => {},
+            other => panic!("expected budget overrun, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_example_pilot() {
+        let mut pilot = ScriptController::from_source(include_str!("../../pilots/seeker.rhai"),
+                                                      &Budget::default())
+            .expect("example pilot compiles");
+        pilot.tick(&view(), &scanner()).expect("example pilot stays within budget");
+    }
+
+    fn write_script(path: &Path, source: &str) {
+        let mut file = File::create(path).expect("created script");
+        file.write_all(source.as_bytes()).expect("wrote script");
+    }
+
+    #[test]
+    fn test_watcher_reload() {
+        let path = env::temp_dir().join("pewpew_test_watcher_reload.rhai");
+        write_script(&path, "fn tick() { fire(); }");
+
+        let mut watcher = PilotWatcher::new(Budget::default());
+        watcher.watch(4, &path).expect("initial script loads");
+        assert!(watcher.poll().is_empty(), "nothing changed yet");
+
+        write_script(&path, "fn tick() { thrust( }");
+        let reloaded = watcher.poll();
+        assert_eq!(1, reloaded.len());
+        assert_eq!(4, reloaded[0].0);
+        assert!(reloaded[0].1.is_err(), "broken script is rejected");
+        assert!(watcher.poll().is_empty(), "errors are only reported once");
+
+        write_script(&path, "fn tick() { torque(vec3(0.0, 0.0, 1.0)); }");
+        let mut reloaded = watcher.poll();
+        let (player, pilot) = reloaded.pop().expect("fixed script reloads");
+        assert_eq!(4, player);
+        let commands = pilot.expect("fixed script compiles").tick(&view(), &scanner()).unwrap();
+        assert_eq!(Vector3::new(0.0, 0.0, 1.0), commands.torque);
+    }
+}
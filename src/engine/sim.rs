@@ -0,0 +1,73 @@
+use na::Vector3;
+
+use engine::engine::Round;
+use game::board::PlayerId;
+use game::ship::Ship;
+
+/// Starting layout for a headless match: where each ship spawns and its initial burn
+pub struct Scenario {
+    pub ships: Vec<(PlayerId, Ship, Vector3<f32>)>,
+}
+
+impl Scenario {
+    /// `count` ships spaced evenly around a circle, each burning towards the center
+    pub fn ring(count: u8, radius: f32) -> Scenario {
+        let mut ships = Vec::with_capacity(count as usize);
+        for i in 0..count {
+            let angle = i as f32 / count as f32 * 2.0 * ::std::f32::consts::PI;
+            let position = Vector3::new(radius * angle.cos(), radius * angle.sin(), 0.0);
+            ships.push((i + 1, Ship::at(position), -position.normalize()));
+        }
+        Scenario { ships: ships }
+    }
+
+    pub fn into_round(self) -> Round {
+        let mut round = Round::new();
+        for (player, ship, burn) in self.ships {
+            round.add_ship(player, ship);
+            round.fire_engine(player, burn);
+        }
+        round
+    }
+}
+
+/// Timing results from a fast-forwarded simulation
+#[derive(Debug)]
+pub struct SimReport {
+    pub ticks: u32,
+    pub ships: usize,
+    pub elapsed_s: f64,
+}
+
+impl SimReport {
+    pub fn ticks_per_s(&self) -> f64 {
+        if self.elapsed_s > 0.0 {
+            self.ticks as f64 / self.elapsed_s
+        } else {
+            0.0
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_ring() {
+        let scenario = Scenario::ring(4, 10.0);
+        assert_eq!(4, scenario.ships.len());
+        for &(_, ref ship, ref burn) in &scenario.ships {
+            assert!((ship.position().norm() - 10.0).abs() < 0.001);
+            assert!(ship.position().dot(burn) < 0.0, "burn points inwards");
+        }
+    }
+
+    #[test]
+    fn test_ticks_per_s() {
+        let report = SimReport { ticks: 100, ships: 2, elapsed_s: 0.5 };
+        assert_eq!(200.0, report.ticks_per_s());
+        let instant = SimReport { ticks: 100, ships: 2, elapsed_s: 0.0 };
+        assert_eq!(0.0, instant.ticks_per_s());
+    }
+}
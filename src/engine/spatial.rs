@@ -0,0 +1,366 @@
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap};
+use std::f32;
+
+use na::{Point3, Vector3};
+use ncollide::bounding_volume::AABB;
+use ncollide::query::Ray;
+use ncollide::world::CollisionGroups;
+use nphysics3d::world::World;
+use nphysics3d::object::{RigidBodyHandle, WorldObject};
+
+use game::board::PlayerId;
+use game::boundary;
+use game::scanner::{RayHit, Terrain};
+
+/// The physics world and the ship bodies in it, with queries backed by its collision world
+pub struct Space {
+    pub world: RefCell<World<f32>>,
+    /// Changed through `add_body` and `remove_body`, so `owners` keeps up
+    pub bodies: RefCell<BTreeMap<PlayerId, RigidBodyHandle<f32>>>,
+    /// Whose ship each body is, by the address of its handle
+    owners: RefCell<HashMap<usize, PlayerId>>,
+    /// Line of sight between pairs of ships, only good until the next physics step
+    sight_lines: RefCell<HashMap<(PlayerId, PlayerId), bool>>,
+    /// Half extents of the box the arena wraps around in, if it does
+    pub wrap: RefCell<Option<Vector3<f32>>>,
+}
+
+impl Space {
+    pub fn new() -> Space {
+        let mut world = World::new();
+        world.set_gravity(Vector3::new(0.0, 0.0, 0.0));
+
+        Space {
+            world: RefCell::new(world),
+            bodies: RefCell::new(BTreeMap::new()),
+            owners: RefCell::new(HashMap::new()),
+            sight_lines: RefCell::new(HashMap::new()),
+            wrap: RefCell::new(None),
+        }
+    }
+
+    pub fn step(&self, dt: f32) {
+        self.world.borrow_mut().step(dt);
+        self.sight_lines.borrow_mut().clear();
+    }
+
+    /// Make `handle` the body of `player`'s ship, in place of any it had before
+    pub fn add_body(&self, player: PlayerId, handle: RigidBodyHandle<f32>) {
+        let mut owners = self.owners.borrow_mut();
+        owners.insert(key(&handle), player);
+        if let Some(old) = self.bodies.borrow_mut().insert(player, handle) {
+            owners.remove(&key(&old));
+        }
+    }
+
+    pub fn remove_body(&self, player: PlayerId) -> Option<RigidBodyHandle<f32>> {
+        let handle = self.bodies.borrow_mut().remove(&player);
+        if let Some(ref handle) = handle {
+            self.owners.borrow_mut().remove(&key(handle));
+        }
+        handle
+    }
+
+    pub fn position(&self, player: PlayerId) -> Option<Vector3<f32>> {
+        self.bodies.borrow().get(&player).map(|rb| rb.borrow().position().translation.vector)
+    }
+
+    /// Ships whose centers are within `radius` of `center`, nearest first
+    /// Candidates come from the broad phase, so only nearby bodies are looked at
+    /// In a wrapping arena every ship is checked, measuring the short way round
+    pub fn ships_within(&self, center: Vector3<f32>, radius: f32) -> Vec<(PlayerId, f32)> {
+        if let Some(half_extents) = *self.wrap.borrow() {
+            let mut found: Vec<(PlayerId, f32)> = self.bodies.borrow().keys()
+                .filter_map(|player| {
+                    self.position(*player)
+                        .map(|position| (*player, boundary::offset(center, position, half_extents).norm()))
+                })
+                .filter(|&(_, dist)| dist <= radius)
+                .collect();
+            found.sort_by(|a, b| a.1.partial_cmp(&b.1).expect("distances aren't NaN"));
+            return found;
+        }
+        let reach = Vector3::new(radius, radius, radius);
+        let mins = center - reach;
+        let maxs = center + reach;
+        let aabb = AABB::new(Point3::new(mins.x, mins.y, mins.z), Point3::new(maxs.x, maxs.y, maxs.z));
+        let groups = CollisionGroups::new();
+
+        let world = self.world.borrow();
+        let mut found: Vec<(PlayerId, f32)> = world.collision_world()
+            .interferences_with_aabb(&aabb, &groups)
+            .filter_map(|object| match object.data {
+                WorldObject::RigidBody(ref rb) => self.owner(rb),
+                _ => None,
+            })
+            .filter_map(|player| {
+                self.position(player)
+                    .map(|position| (player, (position - center).norm()))
+                    .and_then(|(player, dist)| if dist <= radius { Some((player, dist)) } else { None })
+            })
+            .collect();
+        found.sort_by(|a, b| a.1.partial_cmp(&b.1).expect("distances aren't NaN"));
+        found
+    }
+
+    /// The closest ship within `radius` that `accept` is interested in
+    pub fn nearest_ship<F>(&self, center: Vector3<f32>, radius: f32, accept: F) -> Option<(PlayerId, f32)>
+        where F: Fn(PlayerId) -> bool
+    {
+        self.ships_within(center, radius).into_iter().find(|&(player, _)| accept(player))
+    }
+
+    /// The closest thing along a ray that `accept` is interested in, and how far away it is
+    /// In a wrapping arena the ray carries on from the opposite face whenever it leaves the box,
+    /// for at most `MAX_LEGS` crossings. Rays without a finite length don't hit anything
+    pub fn raycast<F>(&self, origin: Vector3<f32>, dir: Vector3<f32>, max_dist: f32, accept: F)
+                      -> Option<(RayHit, f32)>
+        where F: Fn(RayHit) -> bool
+    {
+        let norm = dir.norm();
+        if norm == 0.0 || !norm.is_finite() || !max_dist.is_finite() {
+            return None;
+        }
+        let dir = dir / norm;
+        let half_extents = match *self.wrap.borrow() {
+            Some(half_extents) => half_extents,
+            None => return self.raycast_leg(origin, dir, max_dist, &accept),
+        };
+        let mut origin = boundary::wrap(origin, half_extents);
+        let mut travelled = 0.0;
+        for _ in 0..MAX_LEGS {
+            if travelled >= max_dist {
+                break;
+            }
+            let leg = exit_distance(origin, dir, half_extents).min(max_dist - travelled);
+            if let Some((hit, toi)) = self.raycast_leg(origin, dir, leg, &accept) {
+                return Some((hit, travelled + toi));
+            }
+            travelled += leg;
+            origin = reenter(origin + dir * leg, dir, half_extents);
+        }
+        None
+    }
+
+    /// `raycast` along a unit direction, without wrapping
+    fn raycast_leg<F>(&self, origin: Vector3<f32>, dir: Vector3<f32>, max_dist: f32, accept: &F)
+                      -> Option<(RayHit, f32)>
+        where F: Fn(RayHit) -> bool
+    {
+        let ray = Ray::new(Point3::new(origin.x, origin.y, origin.z), dir);
+        let groups = CollisionGroups::new();
+        let world = self.world.borrow();
+        let closest = world.collision_world().interferences_with_ray(&ray, &groups)
+            .filter(|&(_, ref intersection)| intersection.toi <= max_dist)
+            .filter_map(|(object, intersection)| {
+                let hit = match object.data {
+                    WorldObject::RigidBody(ref rb) => self.owner(rb).map_or(RayHit::Obstacle, RayHit::Ship),
+                    _ => return None,
+                };
+                if accept(hit) { Some((hit, intersection.toi)) } else { None }
+            })
+            .fold(None, |closest, (hit, toi)| {
+                match closest {
+                    Some((_, best)) if best <= toi => closest,
+                    _ => Some((hit, toi)),
+                }
+            });
+        closest
+    }
+
+    /// Whether any obstacle sits between two ships, checked at most once per pair per step
+    pub fn line_of_sight(&self, a: PlayerId, b: PlayerId) -> bool {
+        let key = if a < b { (a, b) } else { (b, a) };
+        if let Some(&visible) = self.sight_lines.borrow().get(&key) {
+            return visible;
+        }
+        let visible = match (self.position(a), self.position(b)) {
+            (Some(from), Some(to)) => {
+                let between = match *self.wrap.borrow() {
+                    Some(half_extents) => boundary::offset(from, to, half_extents),
+                    None => to - from,
+                };
+                self.raycast(from, between, between.norm(), |hit| hit == RayHit::Obstacle).is_none()
+            },
+            _ => false,
+        };
+        self.sight_lines.borrow_mut().insert(key, visible);
+        visible
+    }
+
+    pub fn forget_sight_lines(&self) {
+        self.sight_lines.borrow_mut().clear();
+    }
+
+    fn owner(&self, rb: &RigidBodyHandle<f32>) -> Option<PlayerId> {
+        self.owners.borrow().get(&key(rb)).cloned()
+    }
+}
+
+impl Terrain for Space {
+    fn raycast_obstacles(&self, origin: Vector3<f32>, dir: Vector3<f32>, max_dist: f32) -> Option<f32> {
+        self.raycast(origin, dir, max_dist, |hit| hit == RayHit::Obstacle).map(|(_, dist)| dist)
+    }
+}
+
+/// Shortest step a wrapped ray takes before crossing a face, so it always gets somewhere
+const MIN_LEG: f32 = 1e-3;
+/// Most pieces a wrapped ray is cast in, however long it is and however it's angled
+const MAX_LEGS: usize = 32;
+
+/// How far a ray from inside the box goes along the unit direction `dir` before it leaves
+fn exit_distance(origin: Vector3<f32>, dir: Vector3<f32>, half_extents: Vector3<f32>) -> f32 {
+    let axis = |p: f32, d: f32, h: f32| {
+        if d > 0.0 {
+            (h - p) / d
+        } else if d < 0.0 {
+            (-h - p) / d
+        } else {
+            f32::INFINITY
+        }
+    };
+    axis(origin.x, dir.x, half_extents.x)
+        .min(axis(origin.y, dir.y, half_extents.y))
+        .min(axis(origin.z, dir.z, half_extents.z))
+        .max(MIN_LEG)
+}
+
+/// Carry a ray that's reached a face over to the opposite one
+fn reenter(position: Vector3<f32>, dir: Vector3<f32>, half_extents: Vector3<f32>) -> Vector3<f32> {
+    let axis = |p: f32, d: f32, h: f32| {
+        if d > 0.0 && p >= h - MIN_LEG {
+            p - 2.0 * h
+        } else if d < 0.0 && p <= -h + MIN_LEG {
+            p + 2.0 * h
+        } else {
+            p
+        }
+    };
+    Vector3::new(axis(position.x, dir.x, half_extents.x),
+                 axis(position.y, dir.y, half_extents.y),
+                 axis(position.z, dir.z, half_extents.z))
+}
+
+/// Where a body's shared cell lives, which doesn't change for as long as the body is around
+fn key(handle: &RigidBodyHandle<f32>) -> usize {
+    &**handle as *const _ as usize
+}
+
+#[cfg(test)]
+mod test {
+    use std::rc::Rc;
+    use na::Translation3;
+    use ncollide::shape::Ball;
+    use nphysics3d::object::RigidBody;
+    use super::*;
+
+    fn add_ball(space: &Space, player: Option<PlayerId>, position: Vector3<f32>) {
+        let mut rb = match player {
+            Some(_) => RigidBody::new_dynamic(Ball::new(0.5), 1.0, 0.3, 0.6),
+            None => RigidBody::new_static(Ball::new(2.0), 0.3, 0.6),
+        };
+        rb.append_translation(&Translation3::new(position.x, position.y, position.z));
+        let handle = space.world.borrow_mut().add_rigid_body(rb);
+        if let Some(player) = player {
+            space.add_body(player, handle);
+        }
+    }
+
+    fn space() -> Rc<Space> {
+        let space = Space::new();
+        add_ball(&space, Some(1), Vector3::new(0.0, 0.0, 0.0));
+        add_ball(&space, Some(2), Vector3::new(10.0, 0.0, 0.0));
+        add_ball(&space, Some(3), Vector3::new(0.0, -30.0, 0.0));
+        add_ball(&space, Some(4), Vector3::new(0.0, 50.0, 0.0));
+        add_ball(&space, None, Vector3::new(0.0, 25.0, 0.0)); // between 1 and 4
+        space.step(0.01); // populate the broad phase
+        Rc::new(space)
+    }
+
+    #[test]
+    fn test_ships_within() {
+        let space = space();
+        let found: Vec<PlayerId> = space.ships_within(Vector3::new(0.0, 0.0, 0.0), 40.0)
+            .into_iter().map(|(player, _)| player).collect();
+        assert_eq!(vec![1, 2, 3], found);
+    }
+
+    #[test]
+    fn test_ships_within_wrapped() {
+        let space = space();
+        *space.wrap.borrow_mut() = Some(Vector3::new(50.0, 50.0, 50.0));
+        let found = space.ships_within(Vector3::new(0.0, -35.0, 0.0), 20.0);
+        assert_eq!(vec![3, 4], found.iter().map(|&(player, _)| player).collect::<Vec<_>>());
+        assert!((found[1].1 - 15.0).abs() < 1e-3, "ship 4 is just across the edge");
+    }
+
+    #[test]
+    fn test_nearest_ship() {
+        let space = space();
+        let nearest = space.nearest_ship(Vector3::new(0.0, 0.0, 0.0), 100.0, |p| p != 1);
+        assert_eq!(Some(2), nearest.map(|(player, _)| player));
+        assert!(space.nearest_ship(Vector3::new(0.0, 0.0, 0.0), 5.0, |p| p != 1).is_none());
+    }
+
+    #[test]
+    fn test_raycast() {
+        let space = space();
+        let origin = Vector3::new(0.0, 0.0, 0.0);
+        let up = Vector3::new(0.0, 1.0, 0.0);
+        let (hit, dist) = space.raycast(origin, up, 100.0, |hit| hit != RayHit::Ship(1)).expect("hit");
+        assert_eq!(RayHit::Obstacle, hit);
+        assert!((dist - 23.0).abs() < 0.01, "hit the asteroid's surface: {}", dist);
+
+        let right = Vector3::new(1.0, 0.0, 0.0);
+        let hit = space.raycast(origin, right, 100.0, |hit| hit != RayHit::Ship(1));
+        assert_eq!(Some(RayHit::Ship(2)), hit.map(|(hit, _)| hit));
+        assert!(space.raycast(origin, right, 5.0, |hit| hit != RayHit::Ship(1)).is_none());
+    }
+
+    #[test]
+    fn test_owners() {
+        let space = space();
+        let origin = Vector3::new(0.0, 0.0, 0.0);
+        let right = Vector3::new(1.0, 0.0, 0.0);
+        let handle = space.remove_body(2).expect("ship 2 has a body");
+        assert_eq!(Some(RayHit::Obstacle), space.raycast(origin, right, 100.0, |hit| hit != RayHit::Ship(1))
+            .map(|(hit, _)| hit), "a body nobody owns is just scenery");
+        space.add_body(5, handle);
+        assert_eq!(Some(RayHit::Ship(5)), space.raycast(origin, right, 100.0, |hit| hit != RayHit::Ship(1))
+            .map(|(hit, _)| hit));
+    }
+
+    #[test]
+    fn test_raycast_wrapped() {
+        let space = space();
+        *space.wrap.borrow_mut() = Some(Vector3::new(50.0, 50.0, 50.0));
+        let up = Vector3::new(0.0, 1.0, 0.0);
+        let (hit, dist) = space.raycast(Vector3::new(0.0, 40.0, 0.0), up, 100.0, |hit| hit != RayHit::Ship(4))
+            .expect("hit");
+        assert_eq!(RayHit::Ship(3), hit, "the ray comes back in at the bottom");
+        assert!((dist - 29.5).abs() < 0.01, "{}", dist);
+        assert!(space.raycast(Vector3::new(0.0, 40.0, 0.0), up, 25.0, |hit| hit != RayHit::Ship(4)).is_none());
+
+        let across = Vector3::new(0.0, 0.0, 1.0); // misses everything, forever
+        assert!(space.raycast(Vector3::new(0.0, 40.0, 0.0), across, f32::INFINITY, |_| true).is_none());
+        assert!(space.raycast(Vector3::new(0.0, 40.0, 0.0), across, 1e30, |_| true).is_none());
+    }
+
+    #[test]
+    fn test_line_of_sight_wrapped() {
+        let space = Space::new();
+        *space.wrap.borrow_mut() = Some(Vector3::new(20.0, 20.0, 20.0));
+        add_ball(&space, Some(1), Vector3::new(15.0, 0.0, 0.0));
+        add_ball(&space, Some(2), Vector3::new(-15.0, 0.0, 0.0));
+        space.step(0.01);
+        assert!(space.line_of_sight(1, 2));
+
+        add_ball(&space, None, Vector3::new(-17.0, 0.0, 0.0)); // just across the edge from ship 1
+        space.step(0.01);
+        assert!(!space.line_of_sight(1, 2), "the short way round is blocked");
+        let hit = space.raycast(Vector3::new(15.0, 0.0, 0.0), Vector3::new(1.0, 0.0, 0.0), 100.0,
+                                |hit| hit != RayHit::Ship(1));
+        assert_eq!(Some(RayHit::Obstacle), hit.map(|(hit, _)| hit));
+    }
+}
@@ -0,0 +1,261 @@
+use std::io;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::path::Path;
+use std::process::{Child, Command, Stdio};
+use std::sync::mpsc::{channel, sync_channel, Receiver, RecvTimeoutError, SyncSender, TrySendError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use serde_json;
+
+use engine::json::{JsonCommands, JsonView, MAX_LINE_BYTES};
+use game::controller::{Commands, Fault, SensorView, ShipController};
+use game::scanner::Scanner;
+
+/// How long a bot process gets to answer each tick before it misses its turn
+pub const DEFAULT_DEADLINE_MS: u64 = 5;
+
+/// A pilot running as a separate local process
+///
+/// Every tick the bot is sent its sensor view as a single line of JSON on stdin, and has until
+/// the deadline to answer with a line of JSON commands on stdout that echoes the view's `time`,
+/// e.g. `{"time": 120, "thrust": [1, 0, 0]}`.
+/// Late answers are discarded and the ship does nothing that tick, and so is a view the bot
+/// hasn't got round to reading yet. A line longer than `MAX_LINE_BYTES` counts as a crash.
+pub struct ProcessController {
+    child: Child,
+    /// Views waiting to be written to the bot's stdin, which happens on its own thread
+    views: SyncSender<Vec<u8>>,
+    /// Lines from the bot's stdout, or why it had to stop reading them
+    lines: Receiver<Result<String, String>>,
+    deadline: Duration,
+    crashed: bool,
+}
+
+impl ProcessController {
+    pub fn spawn(program: &Path, deadline: Duration) -> io::Result<ProcessController> {
+        let mut command = Command::new(program);
+        ProcessController::spawn_command(&mut command, deadline)
+    }
+
+    pub fn spawn_command(command: &mut Command, deadline: Duration) -> io::Result<ProcessController> {
+        let mut child = command
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()?;
+        let mut stdin = child.stdin.take().expect("piped stdin");
+        let stdout = child.stdout.take().expect("piped stdout");
+
+        // a bot that stops reading fills the pipe, so writes can't happen on the tick either
+        let (views, pending) = sync_channel::<Vec<u8>>(1);
+        thread::spawn(move || {
+            for view in pending {
+                if stdin.write_all(&view).and_then(|_| stdin.flush()).is_err() {
+                    break;
+                }
+            }
+        });
+
+        // stdout is read on its own thread so a silent bot can't block the tick
+        let (tx, rx) = channel();
+        thread::spawn(move || {
+            let mut reader = BufReader::new(stdout);
+            loop {
+                let mut line = Vec::new();
+                match (&mut reader).take(MAX_LINE_BYTES as u64 + 1).read_until(b'\n', &mut line) {
+                    Ok(0) | Err(_) => break,
+                    Ok(_) => {},
+                }
+                if line.last() == Some(&b'\n') {
+                    line.pop();
+                    if line.last() == Some(&b'\r') {
+                        line.pop();
+                    }
+                } else if line.len() > MAX_LINE_BYTES {
+                    let _ = tx.send(Err(format!("sent a line longer than {} bytes", MAX_LINE_BYTES)));
+                    break;
+                }
+                let line = match String::from_utf8(line) {
+                    Ok(line) => line,
+                    Err(_) => break,
+                };
+                if tx.send(Ok(line)).is_err() {
+                    break;
+                }
+            }
+        });
+
+        Ok(ProcessController {
+            child: child,
+            views: views,
+            lines: rx,
+            deadline: deadline,
+            crashed: false,
+        })
+    }
+
+    fn crash(&mut self, reason: String) -> Result<Commands, Fault> {
+        self.crashed = true;
+        let status = self.child.try_wait().ok().and_then(|s| s).map(|s| s.to_string());
+        Err(Fault::Crashed(format!("{} ({})", reason, status.unwrap_or("still running".to_string()))))
+    }
+}
+
+impl ShipController for ProcessController {
+    fn tick(&mut self, view: &SensorView, _scanner: &Scanner) -> Result<Commands, Fault> {
+        if self.crashed {
+            return Ok(Commands::none()); // already reported, nothing more to say
+        }
+
+        // anything still queued is an answer to an earlier tick that came in too late
+        while self.lines.try_recv().is_ok() {}
+
+        let mut line = serde_json::to_string(&JsonView::from(view)).expect("serializable view");
+        line.push('\n');
+        match self.views.try_send(line.into_bytes()) {
+            Ok(()) => {},
+            Err(TrySendError::Full(_)) => return Err(Fault::Error("still reading an earlier view".to_string())),
+            Err(TrySendError::Disconnected(_)) => return self.crash("couldn't write to bot".to_string()),
+        }
+
+        let deadline = Instant::now() + self.deadline;
+        loop {
+            let now = Instant::now();
+            let left = if deadline > now { deadline - now } else { Duration::from_millis(0) };
+            let answer = match self.lines.recv_timeout(left) {
+                Ok(Ok(answer)) => answer,
+                Ok(Err(reason)) => return self.crash(reason),
+                Err(RecvTimeoutError::Timeout) => return Err(Fault::Error("missed the deadline".to_string())),
+                Err(RecvTimeoutError::Disconnected) => return self.crash("bot closed stdout".to_string()),
+            };
+            let commands = serde_json::from_str::<JsonCommands>(&answer)
+                .map_err(|e| Fault::Error(format!("bad commands {:?}: {}", answer, e)))?;
+            match commands.time {
+                Some(time) if time == view.time => return Ok(Commands::from(commands)),
+                Some(time) if time < view.time => continue, // a late answer to an earlier view
+                _ => return Err(Fault::Error(format!("commands {:?} don't answer the view at {}", answer, view.time))),
+            }
+        }
+    }
+}
+
+impl Drop for ProcessController {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::rc::Rc;
+    use na::Vector3;
+    use engine::spatial::Space;
+    use game::board::Board;
+    use game::controller::Contact;
+    use game::ship::Ship;
+    use super::*;
+
+    fn view() -> SensorView {
+        let mut board = Board::new();
+        board.add_ship(1, Ship::at_origin());
+        SensorView::from_board(&board, 1).unwrap()
+    }
+
+    fn scanner() -> Scanner {
+        Scanner::new(Rc::new(Space::new()), &view(), 100.0)
+    }
+
+    fn shell(script: &str, deadline_ms: u64) -> ProcessController {
+        let mut command = Command::new("sh");
+        command.arg("-c").arg(script);
+        ProcessController::spawn_command(&mut command, Duration::from_millis(deadline_ms))
+            .expect("spawned bot")
+    }
+
+    #[test]
+    fn test_commands() {
+        let mut bot = shell(r#"while read view; do
+            echo "{\"time\": $(echo "$view" | sed 's/.*"time":\([0-9]*\).*/\1/'), \"thrust\": [0, 1, 0], \"fire\": true}"
+        done"#, 1000);
+        for _ in 0..3 {
+            let commands = bot.tick(&view(), &scanner()).expect("bot answered");
+            assert_eq!(Vector3::new(0.0, 1.0, 0.0), commands.thrust);
+            assert!(commands.fire);
+        }
+    }
+
+    #[test]
+    fn test_missed_deadline() {
+        let mut bot = shell("while read view; do sleep 1; done", 10);
+        match bot.tick(&view(), &scanner()) {
+            Err(Fault::Error(_)) => {},
+            other => panic!("expected a missed deadline, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_stale_answers() {
+        // answers the view at time 0 twice and the next one late, the second tick has to skip
+        // the repeat and wait for the right answer
+        let mut bot = shell(r#"read view; echo '{"time": 0}'; echo '{"time": 0}'
+            read view; sleep 0.1; echo '{"time": 10, "fire": true}'; cat > /dev/null"#, 1000);
+        assert!(!bot.tick(&view(), &scanner()).expect("bot answered").fire);
+        let mut later = view();
+        later.time = 10;
+        assert!(bot.tick(&later, &scanner()).expect("bot answered").fire, "stale answer was skipped");
+    }
+
+    #[test]
+    fn test_unanswered_time() {
+        let mut bot = shell(r#"while read view; do echo '{"fire": true}'; done"#, 1000);
+        match bot.tick(&view(), &scanner()) {
+            Err(Fault::Error(_)) => {},
+            other => panic!("expected missing time to be rejected, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_stuck_reader() {
+        // never reads stdin, so once the pipe fills views are dropped instead of blocking the tick
+        let mut bot = shell("sleep 10", 1);
+        let mut big = view();
+        big.contacts = (0..2000).map(|i| Contact {
+            player: 2,
+            team: None,
+            position: Vector3::new(i as f32, 0.0, 0.0),
+            velocity: Vector3::new(0.0, 0.0, 0.0),
+        }).collect();
+        for _ in 0..100 {
+            assert!(bot.tick(&big, &scanner()).is_err());
+        }
+    }
+
+    #[test]
+    fn test_bad_commands() {
+        let mut bot = shell("while read view; do echo 'pew pew'; done", 1000);
+        match bot.tick(&view(), &scanner()) {
+            Err(Fault::Error(_)) => {},
+            other => panic!("expected a parse error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_long_line() {
+        let mut bot = shell("read view; head -c 70000 /dev/zero | tr '\\0' ' '; echo", 1000);
+        match bot.tick(&view(), &scanner()) {
+            Err(Fault::Crashed(reason)) => assert!(reason.contains("longer than"), "{}", reason),
+            other => panic!("expected a crash, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_crash() {
+        let mut bot = shell("exit 3", 1000);
+        match bot.tick(&view(), &scanner()) {
+            Err(Fault::Crashed(_)) => {},
+            other => panic!("expected a crash, got {:?}", other),
+        }
+        assert_eq!(Commands::none(), bot.tick(&view(), &scanner()).expect("crash is only reported once"));
+    }
+}
@@ -0,0 +1,352 @@
+use std::fmt;
+use std::fs::File;
+use std::io;
+use std::io::Read;
+use std::path::Path;
+
+use na::Vector3;
+use wasmi;
+use wasmi::{Caller, Config, Engine, Linker, Module, Store, StoreLimits, StoreLimitsBuilder, TrapCode, TypedFunc};
+
+use game::controller::{Commands, Contact, Fault, SensorView, ShipController};
+use game::scanner::{RayHit, Scanner};
+
+/// Fuel given to a module for each tick, roughly one unit per executed instruction
+pub const FUEL_PER_TICK: u64 = 100_000;
+
+/// Most linear memory a module can have, growing past it fails
+pub const MAX_MEMORY_BYTES: usize = 16 * 1024 * 1024;
+
+/// Module name the host functions are imported from
+const ABI_MODULE: &'static str = "pewpew";
+
+#[derive(Debug)]
+pub enum WasmError {
+    Io(io::Error),
+    Invalid(String),
+}
+
+impl fmt::Display for WasmError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            WasmError::Io(ref e) => write!(f, "couldn't read module: {}", e),
+            WasmError::Invalid(ref e) => write!(f, "couldn't load module: {}", e),
+        }
+    }
+}
+
+impl From<wasmi::Error> for WasmError {
+    fn from(e: wasmi::Error) -> WasmError {
+        WasmError::Invalid(e.to_string())
+    }
+}
+
+struct HostState {
+    view: Option<SensorView>,
+    scanner: Option<Scanner>,
+    commands: Commands,
+    limits: StoreLimits,
+}
+
+/// A pilot compiled to WebAssembly, run by an in-process interpreter
+///
+/// The module must export `tick: [] -> []` and may import any of these from `pewpew`:
+///
+/// * `position(axis: i32) -> f32`, `velocity(axis)`, `heading(axis)`: the ship's own state
+/// * `hull() -> f32`, `time() -> i32`, `team() -> i32`: the ship's team, or -1
+/// * `contact_count() -> i32`, `contact_player(i: i32) -> i32`, `contact_team(i: i32) -> i32`
+/// * `contact_position(i: i32, axis: i32) -> f32`, `contact_velocity(i, axis)`
+/// * `nearest() -> i32`: the closest contact, or -1
+/// * `ships_within(radius: f32) -> i32`: how many contacts are that close
+/// * `raycast(x: f32, y: f32, z: f32, max_dist: f32) -> (f32, i32, i32)`: the distance to the first
+///   thing hit, what it was (1 for a ship, 2 for an obstacle) and the ship's player id; -1, 0 and -1
+///   when nothing's hit
+/// * `thrust(x: f32, y: f32, z: f32)`, `torque(x, y, z)`, `fire()`
+///
+/// Axes are 0, 1, 2 for x, y, z; anything out of range reads as 0.
+/// The ship's imports trap when they're called outside of `tick` (from a start function, say),
+/// and memory can't grow past `MAX_MEMORY_BYTES`.
+pub struct WasmController {
+    store: Store<HostState>,
+    tick: TypedFunc<(), ()>,
+    fuel: u64,
+}
+
+impl WasmController {
+    pub fn load(path: &Path, fuel: u64) -> Result<WasmController, WasmError> {
+        let mut bytes = Vec::new();
+        File::open(path)
+            .and_then(|mut f| f.read_to_end(&mut bytes))
+            .map_err(WasmError::Io)?;
+        WasmController::from_bytes(&bytes, fuel)
+    }
+
+    pub fn from_bytes(bytes: &[u8], fuel: u64) -> Result<WasmController, WasmError> {
+        let mut config = Config::default();
+        config.consume_fuel(true);
+        let engine = Engine::new(&config);
+        let module = Module::new(&engine, bytes)?;
+        let limits = StoreLimitsBuilder::new()
+            .memory_size(MAX_MEMORY_BYTES)
+            .memories(1)
+            .tables(1)
+            .instances(1)
+            .build();
+        let state = HostState { view: None, scanner: None, commands: Commands::none(), limits: limits };
+        let mut store = Store::new(&engine, state);
+        store.limiter(|state| &mut state.limits);
+        let linker = abi_linker(&engine)?;
+        store.set_fuel(fuel)?; // start functions are metered too
+        let instance = linker.instantiate(&mut store, &module)?.start(&mut store)?;
+        let tick = instance.get_typed_func::<(), ()>(&store, "tick")?;
+        Ok(WasmController {
+            store: store,
+            tick: tick,
+            fuel: fuel,
+        })
+    }
+}
+
+impl ShipController for WasmController {
+    fn tick(&mut self, view: &SensorView, scanner: &Scanner) -> Result<Commands, Fault> {
+        {
+            let state = self.store.data_mut();
+            state.view = Some(view.clone());
+            state.scanner = Some(scanner.clone());
+            state.commands = Commands::none();
+        }
+        self.store.set_fuel(self.fuel).map_err(|e| Fault::Error(e.to_string()))?;
+        let result = self.tick.call(&mut self.store, ());
+        self.store.data_mut().scanner = None; // don't hang on to the world between ticks
+        result.map_err(|e| {
+            match e.as_trap_code() {
+                Some(TrapCode::OutOfFuel) => Fault::OverBudget(e.to_string()),
+                _ => Fault::Error(e.to_string()),
+            }
+        })?;
+        Ok(self.store.data().commands.clone())
+    }
+}
+
+fn axis(v: Vector3<f32>, axis: i32) -> f32 {
+    match axis {
+        0 => v.x,
+        1 => v.y,
+        2 => v.z,
+        _ => 0.0,
+    }
+}
+
+fn view<'a>(caller: &'a Caller<HostState>) -> Result<&'a SensorView, wasmi::Error> {
+    caller.data().view.as_ref().ok_or_else(|| wasmi::Error::new("ship called outside of a tick"))
+}
+
+fn scanner<'a>(caller: &'a Caller<HostState>) -> Result<&'a Scanner, wasmi::Error> {
+    caller.data().scanner.as_ref().ok_or_else(|| wasmi::Error::new("ship called outside of a tick"))
+}
+
+fn contact<F>(caller: &Caller<HostState>, i: i32, f: F) -> Result<f32, wasmi::Error>
+    where F: Fn(&Contact) -> f32
+{
+    let view = view(caller)?;
+    if i < 0 {
+        return Ok(0.0);
+    }
+    Ok(view.contacts.get(i as usize).map_or(0.0, f))
+}
+
+fn abi_linker(engine: &Engine) -> Result<Linker<HostState>, wasmi::Error> {
+    let mut linker = Linker::new(engine);
+    linker.func_wrap(ABI_MODULE, "position", |c: Caller<HostState>, a: i32| Ok(axis(view(&c)?.position, a)))?;
+    linker.func_wrap(ABI_MODULE, "velocity", |c: Caller<HostState>, a: i32| Ok(axis(view(&c)?.velocity, a)))?;
+    linker.func_wrap(ABI_MODULE, "heading", |c: Caller<HostState>, a: i32| Ok(axis(view(&c)?.heading(), a)))?;
+    linker.func_wrap(ABI_MODULE, "hull", |c: Caller<HostState>| Ok(view(&c)?.hull))?;
+    linker.func_wrap(ABI_MODULE, "time", |c: Caller<HostState>| Ok(view(&c)?.time as i32))?;
+    linker.func_wrap(ABI_MODULE, "team", |c: Caller<HostState>| {
+        Ok(view(&c)?.team.map_or(-1, |team| team as i32))
+    })?;
+    linker.func_wrap(ABI_MODULE, "contact_count", |c: Caller<HostState>| {
+        Ok(view(&c)?.contacts.len() as i32)
+    })?;
+    linker.func_wrap(ABI_MODULE, "contact_player", |c: Caller<HostState>, i: i32| {
+        let view = view(&c)?;
+        if i < 0 {
+            return Ok(-1);
+        }
+        Ok(view.contacts.get(i as usize).map_or(-1, |contact| contact.player as i32))
+    })?;
+    linker.func_wrap(ABI_MODULE, "contact_team", |c: Caller<HostState>, i: i32| {
+        let view = view(&c)?;
+        if i < 0 {
+            return Ok(-1);
+        }
+        Ok(view.contacts.get(i as usize).and_then(|contact| contact.team).map_or(-1, |team| team as i32))
+    })?;
+    linker.func_wrap(ABI_MODULE, "contact_position", |c: Caller<HostState>, i: i32, a: i32| {
+        contact(&c, i, |contact| axis(contact.position, a))
+    })?;
+    linker.func_wrap(ABI_MODULE, "contact_velocity", |c: Caller<HostState>, i: i32, a: i32| {
+        contact(&c, i, |contact| axis(contact.velocity, a))
+    })?;
+    linker.func_wrap(ABI_MODULE, "nearest", |c: Caller<HostState>| {
+        Ok(scanner(&c)?.nearest().map_or(-1, |(player, _)| player as i32))
+    })?;
+    linker.func_wrap(ABI_MODULE, "ships_within", |c: Caller<HostState>, radius: f32| {
+        Ok(scanner(&c)?.ships_within(radius).len() as i32)
+    })?;
+    linker.func_wrap(ABI_MODULE, "raycast", |c: Caller<HostState>, x: f32, y: f32, z: f32, max_dist: f32| {
+        Ok(match scanner(&c)?.raycast(Vector3::new(x, y, z), max_dist) {
+            Some((RayHit::Ship(player), dist)) => (dist, 1, player as i32),
+            Some((RayHit::Obstacle, dist)) => (dist, 2, -1),
+            None => (-1.0, 0, -1),
+        })
+    })?;
+    linker.func_wrap(ABI_MODULE, "thrust", |mut c: Caller<HostState>, x: f32, y: f32, z: f32| {
+        c.data_mut().commands.thrust = Vector3::new(x, y, z);
+    })?;
+    linker.func_wrap(ABI_MODULE, "torque", |mut c: Caller<HostState>, x: f32, y: f32, z: f32| {
+        c.data_mut().commands.torque = Vector3::new(x, y, z);
+    })?;
+    linker.func_wrap(ABI_MODULE, "fire", |mut c: Caller<HostState>| {
+        c.data_mut().commands.fire = true;
+    })?;
+    Ok(linker)
+}
+
+#[cfg(test)]
+mod test {
+    use std::rc::Rc;
+    use na::Vector3;
+    use wat;
+    use engine::engine::Round;
+    use engine::spatial::Space;
+    use game::board::Board;
+    use game::ship::Ship;
+    use super::*;
+
+    fn view() -> SensorView {
+        let mut board = Board::new();
+        board.add_ship(1, Ship::at_origin());
+        board.add_ship(2, Ship::at(Vector3::new(0.0, 4.0, 0.0)));
+        SensorView::from_board(&board, 1).unwrap()
+    }
+
+    fn scanner() -> Scanner {
+        Scanner::new(Rc::new(Space::new()), &view(), 100.0)
+    }
+
+    fn module(source: &str) -> Vec<u8> {
+        wat::parse_str(source).expect("valid wat")
+    }
+
+    #[test]
+    fn test_commands() {
+        let bytes = module(r#"
+            (module
+              (import "pewpew" "contact_position" (func $contact_position (param i32 i32) (result f32)))
+              (import "pewpew" "thrust" (func $thrust (param f32 f32 f32)))
+              (import "pewpew" "fire" (func $fire))
+              (func (export "tick")
+                (call $thrust (f32.const 0) (call $contact_position (i32.const 0) (i32.const 1)) (f32.const 0))
+                (call $fire)))
+        "#);
+        let mut pilot = WasmController::from_bytes(&bytes, FUEL_PER_TICK).expect("module loads");
+        let commands = pilot.tick(&view(), &scanner()).expect("no fault");
+        assert_eq!(Vector3::new(0.0, 4.0, 0.0), commands.thrust);
+        assert!(commands.fire);
+    }
+
+    #[test]
+    fn test_spatial_queries() {
+        let mut round = Round::new();
+        round.add_ship(1, Ship::at_origin());
+        round.add_ship(2, Ship::at(Vector3::new(10.0, 0.0, 0.0)));
+        round.fast_forward(1);
+
+        let bytes = module(r#"
+            (module
+              (import "pewpew" "nearest" (func $nearest (result i32)))
+              (import "pewpew" "raycast" (func $raycast (param f32 f32 f32 f32) (result f32 i32 i32)))
+              (import "pewpew" "fire" (func $fire))
+              (func (export "tick")
+                (local $kind i32)
+                (local $player i32)
+                (call $raycast (f32.const 1) (f32.const 0) (f32.const 0) (f32.const 50))
+                (local.set $player)
+                (local.set $kind)
+                (drop)
+                (if (i32.and
+                      (i32.eq (call $nearest) (i32.const 2))
+                      (i32.and (i32.eq (local.get $kind) (i32.const 1)) (i32.eq (local.get $player) (i32.const 2))))
+                  (then (call $fire)))))
+        "#);
+        let mut pilot = WasmController::from_bytes(&bytes, FUEL_PER_TICK).expect("module loads");
+        let view = round.sensor_view(1).expect("ship 1 is on the board");
+        assert!(pilot.tick(&view, &round.scanner(&view)).unwrap().fire);
+    }
+
+    #[test]
+    fn test_missing_tick() {
+        let bytes = module("(module)");
+        assert!(WasmController::from_bytes(&bytes, FUEL_PER_TICK).is_err());
+    }
+
+    #[test]
+    fn test_out_of_fuel() {
+        let bytes = module(r#"
+            (module
+              (func (export "tick")
+                (loop $forever (br $forever))))
+        "#);
+        let mut pilot = WasmController::from_bytes(&bytes, 1000).expect("module loads");
+        match pilot.tick(&view(), &scanner()) {
+            Err(Fault::OverBudget(_)) => {},
+            other => panic!("expected fuel exhaustion, got {:?}", other),
+        }
+        // fuel is topped up every tick, so the module is metered the same way next time
+        match pilot.tick(&view(), &scanner()) {
+            Err(Fault::OverBudget(_)) => {},
+            other => panic!("expected fuel exhaustion, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_called_from_start() {
+        let bytes = module(r#"
+            (module
+              (import "pewpew" "hull" (func $hull (result f32)))
+              (func $start (drop (call $hull)))
+              (start $start)
+              (func (export "tick")))
+        "#);
+        assert!(WasmController::from_bytes(&bytes, FUEL_PER_TICK).is_err(), "traps instead of panicking");
+    }
+
+    #[test]
+    fn test_memory_limit() {
+        let pages = (MAX_MEMORY_BYTES / 65536) as i32;
+        let bytes = module(&format!(r#"
+            (module
+              (import "pewpew" "fire" (func $fire))
+              (memory 1)
+              (func (export "tick")
+                (if (i32.eq (memory.grow (i32.const {})) (i32.const -1))
+                  (then (call $fire)))))
+        "#, pages));
+        let mut pilot = WasmController::from_bytes(&bytes, FUEL_PER_TICK).expect("module loads");
+        assert!(pilot.tick(&view(), &scanner()).expect("no fault").fire, "growing past the limit fails");
+
+        let bytes = module(&format!("(module (memory {}) (func (export \"tick\")))", pages + 1));
+        assert!(WasmController::from_bytes(&bytes, FUEL_PER_TICK).is_err(), "and so does starting past it");
+    }
+
+    #[test]
+    fn test_trap() {
+        let bytes = module(r#"(module (func (export "tick") unreachable))"#);
+        let mut pilot = WasmController::from_bytes(&bytes, FUEL_PER_TICK).expect("module loads");
+        match pilot.tick(&view(), &scanner()) {
+            Err(Fault::Error(_)) => {},
+            other => panic!("expected a trap, got {:?}", other),
+        }
+    }
+}
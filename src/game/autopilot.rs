@@ -0,0 +1,129 @@
+use na::Vector3;
+
+use game::board::PlayerId;
+use game::controller::clamp_norm;
+use game::ship::{ship_mass, Ship, MAX_THRUST};
+
+/// Fastest the autopilot will fly, in m/s
+const CRUISE_SPEED: f32 = 20.0;
+/// Fraction of full thrust the autopilot plans its braking around, the rest is for corrections
+const BRAKING_MARGIN: f32 = 0.8;
+/// Fraction of full thrust spent holding an orbit's curve
+const ORBIT_GRIP: f32 = 0.5;
+/// Seconds the autopilot takes to close the gap between the velocity it has and the one it wants
+const RESPONSE_S: f32 = 0.2;
+/// Close to a point, how quickly to close the remaining distance, per second
+const APPROACH_GAIN: f32 = 2.0;
+
+/// High-level orders the server turns into thrust each tick, in place of raw thrust
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub enum Autopilot {
+    /// Fly to a point and stop there
+    GoTo(Vector3<f32>),
+    /// Fly alongside another ship
+    MatchVelocity(PlayerId),
+    /// Stay wherever the ship was when the hold started
+    Hold,
+    /// Circle `center` at `radius`, in whatever plane the ship is already moving in
+    Orbit { center: Vector3<f32>, radius: f32 },
+}
+
+fn max_acceleration() -> f32 {
+    MAX_THRUST / ship_mass()
+}
+
+/// Thrust that brings `ship`'s velocity toward `wanted` as quickly as its engines allow
+fn chase(ship: &Ship, wanted: Vector3<f32>) -> Vector3<f32> {
+    clamp_norm((wanted - ship.velocity()) * (ship_mass() / RESPONSE_S), MAX_THRUST)
+}
+
+/// Thrust that flies `ship` to `point`, never faster than it can still stop
+pub fn go_to(ship: &Ship, point: Vector3<f32>) -> Vector3<f32> {
+    let offset = point - ship.position();
+    let distance = offset.norm();
+    if distance < 1e-4 {
+        return chase(ship, Vector3::new(0.0, 0.0, 0.0));
+    }
+    let braking = (2.0 * BRAKING_MARGIN * max_acceleration() * distance).sqrt();
+    let speed = braking.min(CRUISE_SPEED).min(distance * APPROACH_GAIN);
+    chase(ship, offset * (speed / distance))
+}
+
+/// Thrust that matches `ship`'s velocity to `velocity`
+pub fn match_velocity(ship: &Ship, velocity: Vector3<f32>) -> Vector3<f32> {
+    chase(ship, velocity)
+}
+
+/// Thrust that puts `ship` into, and keeps it in, a circle around `center`
+pub fn orbit(ship: &Ship, center: Vector3<f32>, radius: f32) -> Vector3<f32> {
+    let offset = ship.position() - center;
+    let distance = offset.norm();
+    if distance < 1e-4 {
+        return go_to(ship, center + Vector3::new(radius, 0.0, 0.0));
+    }
+    let outward = offset / distance;
+
+    // keep going around whichever way the ship is already heading
+    let velocity = ship.velocity();
+    let mut tangent = velocity - outward * velocity.dot(&outward);
+    if tangent.norm() < 1e-4 {
+        tangent = outward.cross(&Vector3::new(0.0, 0.0, 1.0));
+        if tangent.norm() < 1e-4 {
+            tangent = outward.cross(&Vector3::new(0.0, 1.0, 0.0));
+        }
+    }
+    let tangent = tangent.normalize();
+
+    let speed = (ORBIT_GRIP * max_acceleration() * radius).sqrt().min(CRUISE_SPEED);
+    let correction = ((radius - distance) * APPROACH_GAIN).max(-CRUISE_SPEED).min(CRUISE_SPEED);
+    let wanted = tangent * speed + outward * correction;
+    let centripetal = -outward * (ship_mass() * speed * speed / distance);
+    clamp_norm(chase(ship, wanted) + centripetal, MAX_THRUST)
+}
+
+#[cfg(test)]
+mod test {
+    use na::Vector3;
+    use game::ship::{Ship, MAX_THRUST};
+    use super::*;
+
+    #[test]
+    fn test_go_to() {
+        let ship = Ship::at(Vector3::new(0.0, 0.0, 0.0));
+        let thrust = go_to(&ship, Vector3::new(0.0, 50.0, 0.0));
+        assert!(thrust.y > 0.0);
+        assert!(thrust.norm() <= MAX_THRUST + 1e-4);
+
+        let thrust = go_to(&ship, ship.position());
+        assert_eq!(Vector3::new(0.0, 0.0, 0.0), thrust, "already stopped on the spot");
+    }
+
+    #[test]
+    fn test_go_to_brakes() {
+        let mut ship = Ship::at(Vector3::new(0.0, 0.0, 0.0));
+        let orientation = ship.orientation();
+        let zero = Vector3::new(0.0, 0.0, 0.0);
+        ship.update(zero, orientation, Vector3::new(0.0, 15.0, 0.0), zero);
+        let thrust = go_to(&ship, Vector3::new(0.0, 2.0, 0.0));
+        assert!(thrust.y < 0.0, "too fast to stop in time, so slow down");
+    }
+
+    #[test]
+    fn test_match_velocity() {
+        let ship = Ship::at(Vector3::new(0.0, 0.0, 0.0));
+        let thrust = match_velocity(&ship, Vector3::new(0.0, 0.0, -1.0));
+        assert!(thrust.z < 0.0);
+        assert_eq!(0.0, thrust.x);
+    }
+
+    #[test]
+    fn test_orbit() {
+        let ship = Ship::at(Vector3::new(10.0, 0.0, 0.0));
+        let thrust = orbit(&ship, Vector3::new(0.0, 0.0, 0.0), 10.0);
+        assert!(thrust.y.abs() > 0.0, "speeds up along the circle");
+        assert!(thrust.norm() <= MAX_THRUST + 1e-4);
+
+        let far = Ship::at(Vector3::new(100.0, 0.0, 0.0));
+        assert!(orbit(&far, Vector3::new(0.0, 0.0, 0.0), 10.0).x < 0.0, "heads in toward the circle");
+    }
+}
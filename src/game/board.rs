@@ -1,21 +1,58 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use bincode::{serialize, Infinite};
 use game::ship::Ship;
 
 pub type PlayerId = u8;
 pub type Timestep = u32;
+pub type TeamId = u8;
 
-#[derive(Serialize, Deserialize, PartialEq, Debug)]
+/// A side players can fight on
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct Team {
+    pub name: String,
+    /// RGB
+    pub color: [u8; 3],
+}
+
+impl Team {
+    pub fn new(name: &str, color: [u8; 3]) -> Team {
+        Team {
+            name: name.to_string(),
+            color: color,
+        }
+    }
+
+    /// Up to four distinct teams, numbered from 1
+    pub fn defaults(count: u8) -> Vec<(TeamId, Team)> {
+        let teams = vec![
+            Team::new("Red", [220, 50, 47]),
+            Team::new("Blue", [38, 139, 210]),
+            Team::new("Green", [133, 153, 0]),
+            Team::new("Yellow", [181, 137, 0]),
+        ];
+        teams.into_iter().take(count as usize).enumerate()
+            .map(|(i, team)| (i as TeamId + 1, team))
+            .collect()
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
 pub struct Board {
-    pub ships: HashMap<PlayerId, Ship>,
+    /// In player order, so everything that goes through the ships does so the same way every time
+    pub ships: BTreeMap<PlayerId, Ship>,
     time: Timestep,
+    pub teams: HashMap<TeamId, Team>,
+    /// Which team each player is on, kept when their ship is destroyed
+    pub members: HashMap<PlayerId, TeamId>,
 }
 
 impl Board {
     pub fn new() -> Board {
         Board {
-            ships: HashMap::new(),
+            ships: BTreeMap::new(),
             time: 0,
+            teams: HashMap::new(),
+            members: HashMap::new(),
         }
     }
 
@@ -23,10 +60,57 @@ impl Board {
         self.ships.insert(player, ship);
     }
 
+    pub fn remove_ship(&mut self, player: PlayerId) -> Option<Ship> {
+        self.ships.remove(&player)
+    }
+
+    pub fn add_team(&mut self, id: TeamId, team: Team) {
+        self.teams.insert(id, team);
+    }
+
+    pub fn team_of(&self, player: PlayerId) -> Option<TeamId> {
+        self.members.get(&player).cloned()
+    }
+
+    /// Whether two different players are on the same team
+    pub fn teammates(&self, a: PlayerId, b: PlayerId) -> bool {
+        a != b && self.team_of(a).is_some() && self.team_of(a) == self.team_of(b)
+    }
+
+    pub fn team_size(&self, team: TeamId) -> usize {
+        self.members.values().filter(|t| **t == team).count()
+    }
+
+    /// Put `player` on `team`, or on the smallest team if they didn't pick one
+    /// Returns the team they ended up on, None if there are no teams or they picked one that
+    /// doesn't exist, in which case they stay on whatever team they were on
+    pub fn join_team(&mut self, player: PlayerId, team: Option<TeamId>) -> Option<TeamId> {
+        if let Some(team) = team {
+            if !self.teams.contains_key(&team) {
+                return None;
+            }
+            self.members.insert(player, team);
+            return Some(team);
+        }
+        self.members.remove(&player);
+        let mut ids: Vec<TeamId> = self.teams.keys().cloned().collect();
+        ids.sort();
+        let team = match ids.into_iter().min_by_key(|team| self.team_size(*team)) {
+            Some(team) => team,
+            None => return None,
+        };
+        self.members.insert(player, team);
+        Some(team)
+    }
+
     pub fn to_bytes(&self) -> Vec<u8> {
         return serialize(self, Infinite).expect("Error serializing game board");
     }
 
+    pub fn time(&self) -> Timestep {
+        self.time
+    }
+
     pub fn advance(&mut self, ms: u32) {
         self.time += ms;
     }
@@ -45,12 +129,37 @@ mod test {
         board.add_ship(2, Ship::at_origin());
 
         let encoded: Vec<u8> = board.to_bytes();
-        assert_eq!(encoded.len(), 134);
+        assert_eq!(encoded.len(), 182);
 
         let decoded: Board = deserialize(&encoded[..]).unwrap();
         assert_eq!(board, decoded);
     }
 
+    #[test]
+    fn test_teams() {
+        let mut board = Board::new();
+        assert_eq!(None, board.join_team(1, None), "no teams to join");
+        for (id, team) in Team::defaults(2) {
+            board.add_team(id, team);
+        }
+        assert_eq!("Blue", board.teams[&2].name);
+
+        assert_eq!(Some(1), board.join_team(1, None));
+        assert_eq!(Some(2), board.join_team(2, None), "smallest team first");
+        assert_eq!(Some(1), board.join_team(3, None));
+        assert_eq!(Some(2), board.join_team(4, Some(2)));
+        assert_eq!(Some(2), board.join_team(5, Some(2)), "picking a team overrides balancing");
+        assert_eq!(None, board.join_team(6, Some(9)));
+        assert_eq!(None, board.team_of(6));
+        assert_eq!(None, board.join_team(5, Some(9)));
+        assert_eq!(Some(2), board.team_of(5), "a bad pick leaves the team alone");
+
+        assert!(board.teammates(1, 3));
+        assert!(!board.teammates(1, 2));
+        assert!(!board.teammates(1, 1));
+        assert_eq!(3, board.team_size(2));
+    }
+
     #[test]
     fn test_advance() {
         let mut board = Board::new();
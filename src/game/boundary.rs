@@ -0,0 +1,159 @@
+use na::Vector3;
+
+use game::map::Bounds;
+
+/// Share of a ship's speed into a wall that it bounces back out with
+const WALL_BOUNCE: f32 = 0.5;
+/// Default push-back, in N per m outside the arena
+const PUSH_STIFFNESS: f32 = 20.0;
+/// Default damage zone: hull lost per second outside, how fast it shrinks and how small it gets
+const ZONE_DAMAGE_PER_S: f32 = 5.0;
+const ZONE_SHRINK_PER_S: f32 = 0.005;
+const ZONE_MIN_SCALE: f32 = 0.2;
+
+/// What happens to ships that leave the map's bounds
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub enum Boundary {
+    /// Nothing, ships can fly off forever
+    Open,
+    /// Solid walls that ships bounce off
+    Walls,
+    /// A spring pulling ships back in, stronger the further out they are
+    PushBack { stiffness: f32 },
+    /// Ships outside take damage, and the arena shrinks as the match goes on
+    Damage { per_second: f32, shrink_per_second: f32, min_scale: f32 },
+    /// Ships leaving one side come back in the other, distances are measured the short way round
+    /// Only box bounds can wrap, spherical arenas get walls instead
+    Wrap,
+}
+
+impl Boundary {
+    /// Boundaries by name, with default strengths
+    pub fn from_name(name: &str) -> Option<Boundary> {
+        match name {
+            "open" => Some(Boundary::Open),
+            "walls" => Some(Boundary::Walls),
+            "push" => Some(Boundary::PushBack { stiffness: PUSH_STIFFNESS }),
+            "zone" => {
+                Some(Boundary::Damage {
+                    per_second: ZONE_DAMAGE_PER_S,
+                    shrink_per_second: ZONE_SHRINK_PER_S,
+                    min_scale: ZONE_MIN_SCALE,
+                })
+            },
+            "wrap" => Some(Boundary::Wrap),
+            _ => None,
+        }
+    }
+
+    /// Half extents of the box ships wrap around in, if they wrap at all
+    pub fn wrap_extents(&self, bounds: Option<&Bounds>) -> Option<Vector3<f32>> {
+        match (self, bounds) {
+            (&Boundary::Wrap, Some(&Bounds::Box { half_extents })) => Some(half_extents),
+            _ => None,
+        }
+    }
+}
+
+/// Where the damage zone's edge is `elapsed_s` into the match
+pub fn zone(bounds: &Bounds, elapsed_s: f32, shrink_per_second: f32, min_scale: f32) -> Bounds {
+    bounds.scaled((1.0 - elapsed_s * shrink_per_second).max(min_scale))
+}
+
+/// A ship outside solid walls, put back on the wall and bounced off it
+/// None if the ship is inside, or already heading back in
+pub fn bounce(bounds: &Bounds, position: Vector3<f32>, velocity: Vector3<f32>)
+              -> Option<(Vector3<f32>, Vector3<f32>)> {
+    if bounds.depth(position) <= 0.0 {
+        return None;
+    }
+    let normal = bounds.outward(position);
+    let outward_speed = velocity.dot(&normal);
+    let velocity = if outward_speed > 0.0 {
+        velocity - normal * (outward_speed * (1.0 + WALL_BOUNCE))
+    } else {
+        velocity
+    };
+    Some((bounds.clamp(position), velocity))
+}
+
+/// Push-back force on a ship at `position`
+pub fn push_back(bounds: &Bounds, position: Vector3<f32>, stiffness: f32) -> Vector3<f32> {
+    let depth = bounds.depth(position);
+    if depth <= 0.0 {
+        return Vector3::new(0.0, 0.0, 0.0);
+    }
+    -bounds.outward(position) * (depth * stiffness)
+}
+
+/// `position` wrapped back into a box centered on the origin
+pub fn wrap(position: Vector3<f32>, half_extents: Vector3<f32>) -> Vector3<f32> {
+    let axis = |p: f32, h: f32| {
+        let size = 2.0 * h;
+        let wrapped = (p + h) % size;
+        (if wrapped < 0.0 { wrapped + size } else { wrapped }) - h
+    };
+    Vector3::new(axis(position.x, half_extents.x),
+                 axis(position.y, half_extents.y),
+                 axis(position.z, half_extents.z))
+}
+
+/// Shortest displacement from `from` to `to` in a box that wraps around
+pub fn offset(from: Vector3<f32>, to: Vector3<f32>, half_extents: Vector3<f32>) -> Vector3<f32> {
+    wrap(to - from, half_extents)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn close(a: Vector3<f32>, b: Vector3<f32>) -> bool {
+        (a - b).norm() < 1e-4
+    }
+
+    #[test]
+    fn test_names() {
+        assert_eq!(Some(Boundary::Walls), Boundary::from_name("walls"));
+        assert_eq!(Some(Boundary::PushBack { stiffness: PUSH_STIFFNESS }), Boundary::from_name("push"));
+        assert_eq!(None, Boundary::from_name("fence"));
+    }
+
+    #[test]
+    fn test_bounce() {
+        let bounds = Bounds::Box { half_extents: Vector3::new(10.0, 10.0, 10.0) };
+        assert_eq!(None, bounce(&bounds, Vector3::new(5.0, 0.0, 0.0), Vector3::new(5.0, 0.0, 0.0)));
+        let (position, velocity) = bounce(&bounds, Vector3::new(12.0, 3.0, 0.0), Vector3::new(4.0, 1.0, 0.0))
+            .expect("outside the walls");
+        assert!(close(Vector3::new(10.0, 3.0, 0.0), position));
+        assert!(close(Vector3::new(-2.0, 1.0, 0.0), velocity));
+    }
+
+    #[test]
+    fn test_push_back() {
+        let bounds = Bounds::Sphere { radius: 10.0 };
+        assert_eq!(Vector3::new(0.0, 0.0, 0.0), push_back(&bounds, Vector3::new(0.0, 9.0, 0.0), 2.0));
+        assert!(close(Vector3::new(0.0, -4.0, 0.0), push_back(&bounds, Vector3::new(0.0, 12.0, 0.0), 2.0)));
+    }
+
+    #[test]
+    fn test_zone_shrinks() {
+        let bounds = Bounds::Sphere { radius: 100.0 };
+        assert_eq!(Bounds::Sphere { radius: 50.0 }, zone(&bounds, 100.0, 0.005, 0.2));
+        assert_eq!(Bounds::Sphere { radius: 20.0 }, zone(&bounds, 1000.0, 0.005, 0.2));
+    }
+
+    #[test]
+    fn test_wrap() {
+        let half = Vector3::new(10.0, 10.0, 10.0);
+        assert!(close(Vector3::new(-9.0, 0.0, 5.0), wrap(Vector3::new(11.0, 0.0, 5.0), half)));
+        assert!(close(Vector3::new(9.0, 0.0, 0.0), wrap(Vector3::new(-11.0, 0.0, 0.0), half)));
+        assert!(close(Vector3::new(3.0, 0.0, 0.0), wrap(Vector3::new(3.0, 0.0, 0.0), half)));
+
+        let across = offset(Vector3::new(9.0, 0.0, 0.0), Vector3::new(-9.0, 0.0, 0.0), half);
+        assert!(close(Vector3::new(2.0, 0.0, 0.0), across), "the short way round");
+
+        let sphere = Bounds::Sphere { radius: 10.0 };
+        assert_eq!(None, Boundary::Wrap.wrap_extents(Some(&sphere)));
+        assert_eq!(None, Boundary::Walls.wrap_extents(Some(&Bounds::Box { half_extents: half })));
+    }
+}
@@ -0,0 +1,195 @@
+use na::{Vector3, Rotation3};
+
+use game::scanner::Scanner;
+use game::autopilot::Autopilot;
+use game::board::{Board, PlayerId, TeamId, Timestep};
+use game::rng::GameRng;
+use game::ship::{MAX_THRUST, MAX_TORQUE};
+
+/// What a ship's sensors report about another ship
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct Contact {
+    pub player: PlayerId,
+    pub position: Vector3<f32>,
+    pub velocity: Vector3<f32>,
+    pub team: Option<TeamId>,
+}
+
+/// A read-only view of the board from the perspective of a single ship
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct SensorView {
+    pub player: PlayerId,
+    pub time: Timestep,
+    pub position: Vector3<f32>,
+    pub orientation: Rotation3<f32>,
+    pub velocity: Vector3<f32>,
+    pub spin: Vector3<f32>,
+    pub hull: f32,
+    pub team: Option<TeamId>,
+    pub contacts: Vec<Contact>,
+}
+
+impl SensorView {
+    /// Build the view for `player`, or None if they don't have a ship on the board
+    pub fn from_board(board: &Board, player: PlayerId) -> Option<SensorView> {
+        board.ships.get(&player).map(|ship| {
+            let contacts = board.ships.iter()
+                .filter(|&(other, _)| *other != player)
+                .map(|(other, ship)| Contact {
+                    player: *other,
+                    position: ship.position(),
+                    velocity: ship.velocity(),
+                    team: board.team_of(*other),
+                })
+                .collect();
+            SensorView {
+                player: player,
+                time: board.time(),
+                position: ship.position(),
+                orientation: ship.orientation(),
+                velocity: ship.velocity(),
+                spin: ship.spin(),
+                hull: ship.hull(),
+                team: board.team_of(player),
+                contacts: contacts,
+            }
+        })
+    }
+
+    /// Unit vector pointing out of the ship's nose
+    pub fn heading(&self) -> Vector3<f32> {
+        self.orientation * Vector3::new(1.0, 0.0, 0.0)
+    }
+}
+
+/// Controls issued to a ship for a single tick
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct Commands {
+    pub thrust: Vector3<f32>,
+    pub torque: Vector3<f32>,
+    pub fire: bool,
+    /// When set, the server works out the thrust and `thrust` is ignored
+    pub autopilot: Option<Autopilot>,
+}
+
+impl Commands {
+    pub fn none() -> Commands {
+        Commands {
+            thrust: Vector3::new(0.0, 0.0, 0.0),
+            torque: Vector3::new(0.0, 0.0, 0.0),
+            fire: false,
+            autopilot: None,
+        }
+    }
+
+    /// Clamp thrust and torque to what the ship's engines can actually deliver
+    /// NaN or infinite thrust or torque is treated as none at all
+    pub fn limited(&self) -> Commands {
+        Commands {
+            thrust: clamp_norm(self.thrust, MAX_THRUST),
+            torque: clamp_norm(self.torque, MAX_TORQUE),
+            fire: self.fire,
+            autopilot: self.autopilot.clone(),
+        }
+    }
+}
+
+/// Scale `v` down so its length is at most `max`, or zero it if it's NaN or infinite
+pub fn clamp_norm(v: Vector3<f32>, max: f32) -> Vector3<f32> {
+    if !v.iter().all(|x| x.is_finite()) {
+        return Vector3::new(0.0, 0.0, 0.0);
+    }
+    let norm = v.norm();
+    if norm > max {
+        v * (max / norm)
+    } else {
+        v
+    }
+}
+
+/// Why a controller didn't produce commands for a tick
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub enum Fault {
+    /// Ran past its per-tick execution or memory budget
+    OverBudget(String),
+    /// Failed for any other reason, the ship just skips its turn
+    Error(String),
+    /// Died for good, reported once and then the ship drifts
+    Crashed(String),
+}
+
+/// How a controller has been misbehaving lately, see `Round::record_fault`
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug, Default)]
+pub struct FaultRecord {
+    /// Budget overruns that haven't been forgiven yet
+    pub strikes: u32,
+    /// Clean ticks in a row since the last overrun
+    pub clean_ticks: u32,
+    /// The fault last reported, it isn't reported again until something changes
+    pub last: Option<Fault>,
+}
+
+/// Anything that can fly a ship: built-in AIs, scripts, remote players
+/// `Round` calls `tick` once per physics step for every registered controller, along with a
+/// scanner for running spatial queries from the ship's point of view
+pub trait ShipController {
+    fn tick(&mut self, view: &SensorView, scanner: &Scanner) -> Result<Commands, Fault>;
+
+    /// Start over from randomness forked off the round's, whenever the controller is added to a
+    /// round or the round is reseeded. Controllers that don't roll dice can ignore it
+    fn reseed(&mut self, _rng: GameRng) {}
+}
+
+#[cfg(test)]
+mod test {
+    use std::f32;
+    use na::Vector3;
+    use game::ship::Ship;
+    use super::*;
+
+    #[test]
+    fn test_view_from_board() {
+        let mut board = Board::new();
+        board.add_ship(1, Ship::at_origin());
+        board.add_ship(2, Ship::at(Vector3::new(5.0, 0.0, 0.0)));
+
+        let view = SensorView::from_board(&board, 1).expect("player 1 has a ship");
+        assert_eq!(1, view.player);
+        assert_eq!(1, view.contacts.len());
+        assert_eq!(2, view.contacts[0].player);
+        assert_eq!(Vector3::new(5.0, 0.0, 0.0), view.contacts[0].position);
+
+        assert!(SensorView::from_board(&board, 3).is_none());
+    }
+
+    #[test]
+    fn test_limited() {
+        let commands = Commands {
+            thrust: Vector3::new(MAX_THRUST * 3.0, 0.0, 0.0),
+            torque: Vector3::new(0.0, 0.0, MAX_TORQUE / 2.0),
+            fire: true,
+            autopilot: Some(Autopilot::Hold),
+        };
+        let limited = commands.limited();
+        assert_eq!(Vector3::new(MAX_THRUST, 0.0, 0.0), limited.thrust);
+        assert_eq!(commands.torque, limited.torque);
+        assert!(limited.fire);
+        assert_eq!(Some(Autopilot::Hold), limited.autopilot);
+    }
+
+    #[test]
+    fn test_limited_not_finite() {
+        let commands = Commands {
+            thrust: Vector3::new(f32::NAN, 1.0, 0.0),
+            torque: Vector3::new(0.0, f32::INFINITY, 0.0),
+            fire: true,
+            autopilot: None,
+        };
+        let limited = commands.limited();
+        assert_eq!(Vector3::new(0.0, 0.0, 0.0), limited.thrust);
+        assert_eq!(Vector3::new(0.0, 0.0, 0.0), limited.torque);
+        assert!(limited.fire);
+        let huge = Commands { thrust: Vector3::new(f32::MAX, f32::MAX, 0.0), ..Commands::none() };
+        assert_eq!(Vector3::new(0.0, 0.0, 0.0), huge.limited().thrust, "its length overflows");
+    }
+}
@@ -0,0 +1,17 @@
+use game::board::PlayerId;
+use game::controller::Fault;
+
+/// Something notable that happened during a round
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub enum Event {
+    Hit { shooter: PlayerId, target: PlayerId, damage: f32 },
+    Destroyed { player: PlayerId, by: Option<PlayerId> },
+    /// A ship's controller failed and the ship skipped a turn
+    Faulted { player: PlayerId, fault: Fault },
+    /// A ship was removed from the round for repeatedly breaking its budget
+    Disqualified { player: PlayerId },
+    /// A changed pilot script was swapped in
+    PilotReloaded { player: PlayerId },
+    /// A changed pilot script didn't compile, the previous version keeps flying
+    PilotRejected { player: PlayerId, error: String },
+}
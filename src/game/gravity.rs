@@ -0,0 +1,112 @@
+use na::Vector3;
+
+use game::ship::SHIP_RADIUS;
+
+/// Closer than this the pull is left out, rather than blowing up
+const MIN_DISTANCE: f32 = 1e-3;
+
+/// A planet or star pulling every ship towards it
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct Well {
+    pub position: Vector3<f32>,
+    /// Gravitational parameter, the pull at distance `d` is `strength / d²` m/s²
+    pub strength: f32,
+    /// Surface of the planet, ships that touch it are destroyed
+    pub radius: f32,
+}
+
+impl Well {
+    /// Acceleration due to this well at `position`
+    pub fn pull(&self, position: Vector3<f32>) -> Vector3<f32> {
+        let offset = self.position - position;
+        let distance = offset.norm();
+        if distance < MIN_DISTANCE {
+            return Vector3::new(0.0, 0.0, 0.0);
+        }
+        offset * (self.strength / (distance * distance * distance))
+    }
+
+    /// Whether a ship at `position` has hit the surface
+    pub fn crashed(&self, position: Vector3<f32>) -> bool {
+        (position - self.position).norm() <= self.radius + SHIP_RADIUS
+    }
+
+    /// Speed needed for a circular orbit at `distance` from the center
+    pub fn orbital_speed(&self, distance: f32) -> f32 {
+        (self.strength / distance).sqrt()
+    }
+}
+
+/// Total acceleration due to every well at `position`
+pub fn acceleration(wells: &[Well], position: Vector3<f32>) -> Vector3<f32> {
+    wells.iter().fold(Vector3::new(0.0, 0.0, 0.0), |total, well| total + well.pull(position))
+}
+
+pub fn crashed(wells: &[Well], position: Vector3<f32>) -> bool {
+    wells.iter().any(|well| well.crashed(position))
+}
+
+/// Where a coasting ship will be over the next `steps` steps of `dt_s` seconds each
+/// Integrates the same way the physics engine does (velocity first, then position),
+/// and stops early if the ship would crash into a well
+pub fn predict(wells: &[Well], position: Vector3<f32>, velocity: Vector3<f32>, dt_s: f32, steps: usize)
+               -> Vec<Vector3<f32>> {
+    let mut path = Vec::with_capacity(steps);
+    let mut position = position;
+    let mut velocity = velocity;
+    for _ in 0..steps {
+        velocity = velocity + acceleration(wells, position) * dt_s;
+        position = position + velocity * dt_s;
+        path.push(position);
+        if crashed(wells, position) {
+            break;
+        }
+    }
+    path
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn star() -> Well {
+        Well { position: Vector3::new(0.0, 0.0, 0.0), strength: 10_000.0, radius: 10.0 }
+    }
+
+    #[test]
+    fn test_inverse_square() {
+        let near = star().pull(Vector3::new(50.0, 0.0, 0.0));
+        let far = star().pull(Vector3::new(100.0, 0.0, 0.0));
+        assert_eq!(Vector3::new(-4.0, 0.0, 0.0), near);
+        assert!((near.norm() / far.norm() - 4.0).abs() < 1e-4);
+        assert_eq!(Vector3::new(0.0, 0.0, 0.0), star().pull(Vector3::new(0.0, 0.0, 0.0)));
+
+        let pair = [star(), Well { position: Vector3::new(200.0, 0.0, 0.0), ..star() }];
+        assert_eq!(Vector3::new(0.0, 0.0, 0.0), acceleration(&pair, Vector3::new(100.0, 0.0, 0.0)));
+    }
+
+    #[test]
+    fn test_crashed() {
+        assert!(star().crashed(Vector3::new(0.0, 10.0, 0.0)));
+        assert!(!star().crashed(Vector3::new(0.0, 20.0, 0.0)));
+    }
+
+    #[test]
+    fn test_predict_orbit() {
+        let wells = [star()];
+        let speed = star().orbital_speed(100.0);
+        let path = predict(&wells, Vector3::new(100.0, 0.0, 0.0), Vector3::new(0.0, speed, 0.0), 0.01, 2000);
+        assert_eq!(2000, path.len());
+        for point in &path {
+            assert!((point.norm() - 100.0).abs() < 2.0, "stays in orbit: {:?}", point);
+        }
+    }
+
+    #[test]
+    fn test_predict_crash() {
+        let wells = [star()];
+        let path = predict(&wells, Vector3::new(100.0, 0.0, 0.0), Vector3::new(0.0, 0.0, 0.0), 0.01, 10_000);
+        assert!(path.len() < 10_000, "falls in");
+        assert!(crashed(&wells, *path.last().unwrap()));
+    }
+}
@@ -0,0 +1,477 @@
+use std::fmt;
+use std::fs::File;
+use std::io;
+use std::io::Read;
+use std::path::Path;
+
+use na::Vector3;
+use toml;
+
+use game::board::{PlayerId, TeamId};
+use game::gravity::Well;
+use game::obstacle::Obstacle;
+
+/// How far apart ships that have to share a spawn point start, a few ship lengths
+pub const SPAWN_SPACING: f32 = 3.0;
+
+#[derive(Debug)]
+pub enum MapError {
+    Io(io::Error),
+    Parse(String),
+    Invalid(String),
+}
+
+impl fmt::Display for MapError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            MapError::Io(ref e) => write!(f, "couldn't read map: {}", e),
+            MapError::Parse(ref e) => write!(f, "couldn't parse map: {}", e),
+            MapError::Invalid(ref e) => write!(f, "invalid map: {}", e),
+        }
+    }
+}
+
+/// The edge of the arena, centered on the origin
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub enum Bounds {
+    Sphere { radius: f32 },
+    Box { half_extents: Vector3<f32> },
+}
+
+impl Bounds {
+    pub fn contains(&self, point: Vector3<f32>) -> bool {
+        match *self {
+            Bounds::Sphere { radius } => point.norm() <= radius,
+            Bounds::Box { half_extents } => {
+                point.x.abs() <= half_extents.x && point.y.abs() <= half_extents.y &&
+                    point.z.abs() <= half_extents.z
+            },
+        }
+    }
+
+    /// How far outside the bounds `point` is, negative when it's inside
+    pub fn depth(&self, point: Vector3<f32>) -> f32 {
+        match *self {
+            Bounds::Sphere { radius } => point.norm() - radius,
+            Bounds::Box { half_extents } => {
+                let excess = excess(point, half_extents);
+                excess.x.max(excess.y).max(excess.z)
+            },
+        }
+    }
+
+    /// Unit vector out through the nearest edge to `point`
+    pub fn outward(&self, point: Vector3<f32>) -> Vector3<f32> {
+        match *self {
+            Bounds::Sphere { .. } => {
+                if point.norm() > 0.0 { point.normalize() } else { Vector3::new(1.0, 0.0, 0.0) }
+            },
+            Bounds::Box { half_extents } => {
+                let excess = excess(point, half_extents);
+                let sign = |v: f32| if v < 0.0 { -1.0 } else { 1.0 };
+                if excess.x >= excess.y && excess.x >= excess.z {
+                    Vector3::new(sign(point.x), 0.0, 0.0)
+                } else if excess.y >= excess.z {
+                    Vector3::new(0.0, sign(point.y), 0.0)
+                } else {
+                    Vector3::new(0.0, 0.0, sign(point.z))
+                }
+            },
+        }
+    }
+
+    /// The closest point to `point` inside the bounds
+    pub fn clamp(&self, point: Vector3<f32>) -> Vector3<f32> {
+        match *self {
+            Bounds::Sphere { radius } => {
+                if point.norm() > radius { point.normalize() * radius } else { point }
+            },
+            Bounds::Box { half_extents } => {
+                let axis = |p: f32, h: f32| p.max(-h).min(h);
+                Vector3::new(axis(point.x, half_extents.x),
+                             axis(point.y, half_extents.y),
+                             axis(point.z, half_extents.z))
+            },
+        }
+    }
+
+    /// The same shape, `factor` times the size
+    pub fn scaled(&self, factor: f32) -> Bounds {
+        match *self {
+            Bounds::Sphere { radius } => Bounds::Sphere { radius: radius * factor },
+            Bounds::Box { half_extents } => Bounds::Box { half_extents: half_extents * factor },
+        }
+    }
+}
+
+/// Somewhere ships start, for one team or (with no team) for anyone
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct Spawn {
+    pub team: Option<TeamId>,
+    pub position: Vector3<f32>,
+}
+
+/// A named region game modes can give meaning to, e.g. a hill to hold
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct Zone {
+    pub name: String,
+    pub position: Vector3<f32>,
+    pub radius: f32,
+}
+
+impl Zone {
+    pub fn contains(&self, point: Vector3<f32>) -> bool {
+        (point - self.position).norm() <= self.radius
+    }
+}
+
+/// An arena: its edges, the scenery and planets in it, where ships start and any objective zones
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct Map {
+    pub name: String,
+    /// None for open space that goes on forever
+    pub bounds: Option<Bounds>,
+    pub obstacles: Vec<Obstacle>,
+    pub wells: Vec<Well>,
+    pub spawns: Vec<Spawn>,
+    pub zones: Vec<Zone>,
+}
+
+/// On-disk layout of a map, vectors are plain `[x, y, z]` arrays
+///
+/// ```toml
+/// name = "Belt"
+/// bounds = { radius = 200.0 }          # or { half_extents = [x, y, z] }
+///
+/// [[asteroid]]
+/// position = [40.0, 0.0, 0.0]
+/// radius = 5.0
+///
+/// [[block]]
+/// position = [0.0, 60.0, 0.0]
+/// half_extents = [10.0, 2.0, 2.0]
+///
+/// [[station]]                          # a cylinder standing along the y axis
+/// position = [0.0, 0.0, 0.0]
+/// radius = 8.0
+/// half_height = 20.0
+///
+/// [[well]]                             # a planet, with a pull of strength / distance² m/s²
+/// position = [150.0, 150.0, 0.0]
+/// strength = 20000.0
+/// radius = 15.0
+///
+/// [[spawn]]
+/// team = 1                             # leave out for a spawn anyone can use
+/// position = [-100.0, 0.0, 0.0]
+///
+/// [[zone]]
+/// name = "center"
+/// position = [0.0, 0.0, 0.0]
+/// radius = 25.0
+/// ```
+#[derive(Deserialize)]
+struct MapFile {
+    #[serde(default)]
+    name: String,
+    bounds: Option<BoundsFile>,
+    #[serde(default)]
+    asteroid: Vec<AsteroidFile>,
+    #[serde(default)]
+    block: Vec<BlockFile>,
+    #[serde(default)]
+    station: Vec<StationFile>,
+    #[serde(default)]
+    well: Vec<WellFile>,
+    #[serde(default)]
+    spawn: Vec<SpawnFile>,
+    #[serde(default)]
+    zone: Vec<ZoneFile>,
+}
+
+#[derive(Deserialize)]
+struct BoundsFile {
+    radius: Option<f32>,
+    half_extents: Option<[f32; 3]>,
+}
+
+#[derive(Deserialize)]
+struct AsteroidFile {
+    position: [f32; 3],
+    radius: f32,
+}
+
+#[derive(Deserialize)]
+struct BlockFile {
+    position: [f32; 3],
+    half_extents: [f32; 3],
+}
+
+#[derive(Deserialize)]
+struct StationFile {
+    position: [f32; 3],
+    radius: f32,
+    half_height: f32,
+}
+
+#[derive(Deserialize)]
+struct WellFile {
+    position: [f32; 3],
+    strength: f32,
+    radius: f32,
+}
+
+#[derive(Deserialize)]
+struct SpawnFile {
+    team: Option<TeamId>,
+    position: [f32; 3],
+}
+
+#[derive(Deserialize)]
+struct ZoneFile {
+    name: String,
+    position: [f32; 3],
+    radius: f32,
+}
+
+/// How far past each face of a box `point` is, per axis
+fn excess(point: Vector3<f32>, half_extents: Vector3<f32>) -> Vector3<f32> {
+    Vector3::new(point.x.abs() - half_extents.x,
+                 point.y.abs() - half_extents.y,
+                 point.z.abs() - half_extents.z)
+}
+
+fn vector(a: [f32; 3]) -> Vector3<f32> {
+    Vector3::new(a[0], a[1], a[2])
+}
+
+fn positive(what: &str, value: f32) -> Result<f32, MapError> {
+    if value > 0.0 {
+        Ok(value)
+    } else {
+        Err(MapError::Invalid(format!("{} must be positive, got {}", what, value)))
+    }
+}
+
+impl Map {
+    /// Open space with nothing in it
+    pub fn empty() -> Map {
+        Map {
+            name: String::new(),
+            bounds: None,
+            obstacles: Vec::new(),
+            wells: Vec::new(),
+            spawns: Vec::new(),
+            zones: Vec::new(),
+        }
+    }
+
+    pub fn load(path: &Path) -> Result<Map, MapError> {
+        let mut source = String::new();
+        File::open(path)
+            .and_then(|mut f| f.read_to_string(&mut source))
+            .map_err(MapError::Io)?;
+        Map::from_toml(&source)
+    }
+
+    pub fn from_toml(source: &str) -> Result<Map, MapError> {
+        let file: MapFile = toml::from_str(source).map_err(|e| MapError::Parse(e.to_string()))?;
+
+        let bounds = match file.bounds {
+            None => None,
+            Some(BoundsFile { radius: Some(radius), half_extents: None }) => {
+                Some(Bounds::Sphere { radius: positive("bounds radius", radius)? })
+            },
+            Some(BoundsFile { radius: None, half_extents: Some(half_extents) }) => {
+                for extent in &half_extents {
+                    positive("bounds half extents", *extent)?;
+                }
+                Some(Bounds::Box { half_extents: vector(half_extents) })
+            },
+            Some(_) => {
+                return Err(MapError::Invalid("bounds need a radius or half_extents, not both".to_string()));
+            },
+        };
+
+        let mut obstacles = Vec::new();
+        for asteroid in file.asteroid {
+            obstacles.push(Obstacle::Asteroid {
+                position: vector(asteroid.position),
+                radius: positive("asteroid radius", asteroid.radius)?,
+            });
+        }
+        for block in file.block {
+            for extent in &block.half_extents {
+                positive("block half extents", *extent)?;
+            }
+            obstacles.push(Obstacle::Block {
+                position: vector(block.position),
+                half_extents: vector(block.half_extents),
+            });
+        }
+        for station in file.station {
+            obstacles.push(Obstacle::Station {
+                position: vector(station.position),
+                radius: positive("station radius", station.radius)?,
+                half_height: positive("station half height", station.half_height)?,
+            });
+        }
+
+        let mut wells = Vec::new();
+        for well in file.well {
+            wells.push(Well {
+                position: vector(well.position),
+                strength: positive("well strength", well.strength)?,
+                radius: positive("well radius", well.radius)?,
+            });
+        }
+
+        let mut zones = Vec::new();
+        for zone in file.zone {
+            zones.push(Zone {
+                radius: positive("zone radius", zone.radius)?,
+                name: zone.name,
+                position: vector(zone.position),
+            });
+        }
+
+        Ok(Map {
+            name: file.name,
+            bounds: bounds,
+            obstacles: obstacles,
+            wells: wells,
+            spawns: file.spawn.into_iter()
+                .map(|spawn| Spawn { team: spawn.team, position: vector(spawn.position) })
+                .collect(),
+            zones: zones,
+        })
+    }
+
+    /// Where the `index`th ship on `team` starts, cycling through the team's spawns and stacking
+    /// ships that come back round to one, see `stacked`
+    /// Falls back to the spawns anyone can use, None if there aren't any of those either
+    pub fn spawn_point(&self, team: Option<TeamId>, index: usize) -> Option<Vector3<f32>> {
+        let mut spawns: Vec<&Spawn> = self.spawns.iter()
+            .filter(|spawn| team.is_some() && spawn.team == team)
+            .collect();
+        if spawns.is_empty() {
+            spawns = self.spawns.iter().filter(|spawn| spawn.team.is_none()).collect();
+        }
+        if spawns.is_empty() {
+            return None;
+        }
+        Some(stacked(spawns[index % spawns.len()].position, index / spawns.len()))
+    }
+
+    pub fn zone(&self, name: &str) -> Option<&Zone> {
+        self.zones.iter().find(|zone| zone.name == name)
+    }
+}
+
+/// Where the `lap`th ship to use a spawn point goes: the first right on it, the rest stacked above
+/// and below it in turn, `SPAWN_SPACING` apart
+pub fn stacked(position: Vector3<f32>, lap: usize) -> Vector3<f32> {
+    let rung = ((lap + 1) / 2) as f32 * SPAWN_SPACING;
+    let side = if lap % 2 == 1 { 1.0 } else { -1.0 };
+    position + Vector3::new(0.0, 0.0, side * rung)
+}
+
+/// Players in `zone`, given where everyone is
+pub fn players_in<I>(zone: &Zone, positions: I) -> Vec<PlayerId>
+    where I: Iterator<Item = (PlayerId, Vector3<f32>)>
+{
+    let mut players: Vec<PlayerId> = positions.filter(|&(_, position)| zone.contains(position))
+        .map(|(player, _)| player)
+        .collect();
+    players.sort();
+    players
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_example_map() {
+        let map = Map::from_toml(include_str!("../../maps/belt.toml")).expect("example map loads");
+        assert_eq!("Belt", map.name);
+        assert_eq!(Some(Bounds::Sphere { radius: 250.0 }), map.bounds);
+        assert!(map.obstacles.iter().any(|o| match *o { Obstacle::Station { .. } => true, _ => false }));
+        assert!(map.zone("center").expect("center zone").contains(Vector3::new(5.0, 0.0, 0.0)));
+    }
+
+    #[test]
+    fn test_wells() {
+        let map = Map::from_toml(include_str!("../../maps/star.toml")).expect("star map loads");
+        assert_eq!(2, map.wells.len());
+        assert_eq!(20000.0, map.wells[0].strength);
+        match Map::from_toml("[[well]]\nposition = [0.0, 0.0, 0.0]\nstrength = 10.0\nradius = 0.0") {
+            Err(MapError::Invalid(_)) => (),
+            other => panic!("expected an invalid map, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_spawn_points() {
+        let map = Map::from_toml(r#"
+            [[spawn]]
+            team = 1
+            position = [-100.0, 0.0, 0.0]
+
+            [[spawn]]
+            team = 1
+            position = [-100.0, 20.0, 0.0]
+
+            [[spawn]]
+            position = [0.0, 100.0, 0.0]
+        "#).unwrap();
+        assert_eq!(None, map.bounds);
+        assert_eq!(Some(Vector3::new(-100.0, 20.0, 0.0)), map.spawn_point(Some(1), 1));
+        assert_eq!(Some(Vector3::new(-100.0, 0.0, SPAWN_SPACING)), map.spawn_point(Some(1), 2),
+                   "spawns are reused, but not by two ships in the same place");
+        assert_eq!(Some(Vector3::new(-100.0, 20.0, -SPAWN_SPACING)), map.spawn_point(Some(1), 5));
+        assert_eq!(Some(Vector3::new(0.0, 100.0, 0.0)), map.spawn_point(Some(2), 0), "no team spawns");
+        assert_eq!(Some(Vector3::new(0.0, 100.0, 3.0 * SPAWN_SPACING)), map.spawn_point(None, 5));
+        assert_eq!(None, Map::empty().spawn_point(None, 0));
+    }
+
+    #[test]
+    fn test_bad_maps() {
+        match Map::from_toml("bounds = { radius = 10.0, half_extents = [1.0, 1.0, 1.0] }") {
+            Err(MapError::Invalid(_)) => (),
+            other => panic!("expected an invalid map, got {:?}", other),
+        }
+        match Map::from_toml("[[asteroid]]\nposition = [0.0, 0.0, 0.0]\nradius = -1.0") {
+            Err(MapError::Invalid(_)) => (),
+            other => panic!("expected an invalid map, got {:?}", other),
+        }
+        match Map::from_toml("[[asteroid]]\nradius = 1.0") {
+            Err(MapError::Parse(_)) => (),
+            other => panic!("expected a parse error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_bounds() {
+        let sphere = Bounds::Sphere { radius: 10.0 };
+        assert!(sphere.contains(Vector3::new(0.0, 9.0, 0.0)));
+        assert!(!sphere.contains(Vector3::new(8.0, 8.0, 0.0)));
+        let cube = Bounds::Box { half_extents: Vector3::new(10.0, 10.0, 1.0) };
+        assert!(cube.contains(Vector3::new(8.0, 8.0, 0.0)));
+        assert!(!cube.contains(Vector3::new(0.0, 0.0, 2.0)));
+
+        assert_eq!(2.0, cube.depth(Vector3::new(0.0, 0.0, 3.0)));
+        assert_eq!(Vector3::new(0.0, 0.0, 1.0), cube.outward(Vector3::new(0.0, 0.0, 3.0)));
+        assert_eq!(Vector3::new(10.0, -10.0, 1.0), cube.clamp(Vector3::new(20.0, -12.0, 3.0)));
+        assert_eq!(Vector3::new(0.0, 10.0, 0.0), sphere.clamp(Vector3::new(0.0, 15.0, 0.0)));
+        assert!(sphere.depth(Vector3::new(0.0, 5.0, 0.0)) < 0.0);
+    }
+
+    #[test]
+    fn test_players_in() {
+        let zone = Zone { name: "hill".to_string(), position: Vector3::new(10.0, 0.0, 0.0), radius: 5.0 };
+        let positions = vec![(3, Vector3::new(12.0, 0.0, 0.0)), (1, Vector3::new(0.0, 0.0, 0.0)),
+                             (2, Vector3::new(10.0, 4.0, 0.0))];
+        assert_eq!(vec![2, 3], players_in(&zone, positions.into_iter()));
+    }
+}
@@ -0,0 +1,236 @@
+use std::f32::consts::PI;
+
+use na::{Rotation3, Vector3};
+use rand::Rng;
+
+use game::board::TeamId;
+use game::gravity::Well;
+use game::map::{Bounds, Map, Spawn};
+use game::obstacle::Obstacle;
+use game::rng::GameRng;
+
+/// Spawns are this far out from the center, as a fraction of the arena's radius
+const SPAWN_DISTANCE: f32 = 0.75;
+/// Gap between ships spawning on the same side
+const SIDE_SPAWN_GAP: f32 = 15.0;
+/// Empty space kept around every spawn, and between asteroids and the surface of wells
+const CLEARANCE: f32 = 20.0;
+/// Wells after the first go in a ring this far out, as a fraction of the arena's radius
+const WELL_RING: f32 = 0.35;
+/// Attempts at placing each asteroid before giving up on it
+const PLACEMENT_TRIES: usize = 20;
+
+/// Knobs for `generate`
+#[derive(Clone, PartialEq, Debug)]
+pub struct Params {
+    /// The arena is a sphere this big
+    pub radius: f32,
+    /// Asteroids per million cubic meters
+    pub density: f32,
+    /// Asteroid sizes, small ones are more common than big ones
+    pub min_asteroid: f32,
+    pub max_asteroid: f32,
+    /// Wells come a ring of them at a time, one per side, with one more in the middle if the
+    /// count doesn't split evenly between the sides
+    pub wells: usize,
+    pub well_strength: f32,
+    pub well_radius: f32,
+    /// The arena looks the same from each side, there's at least one
+    pub sides: u8,
+    pub spawns_per_side: u8,
+    /// Give each side's spawns to a team (numbered from 1), rather than to anyone
+    pub team_spawns: bool,
+}
+
+impl Default for Params {
+    fn default() -> Params {
+        Params {
+            radius: 250.0,
+            density: 2.0,
+            min_asteroid: 2.0,
+            max_asteroid: 15.0,
+            wells: 0,
+            well_strength: 20_000.0,
+            well_radius: 15.0,
+            sides: 2,
+            spawns_per_side: 2,
+            team_spawns: false,
+        }
+    }
+}
+
+/// Build an arena from `seed`, the same seed and parameters always give the same map
+///
+/// Everything is laid out with rotational symmetry around the z axis, one copy per side,
+/// so no side starts with better cover or a better slingshot than any other.
+pub fn generate(seed: u32, params: &Params) -> Map {
+    let mut rng = GameRng::new(seed);
+    let sides = params.sides.max(1);
+    let turns: Vec<Rotation3<f32>> = (0..sides)
+        .map(|side| Rotation3::new(Vector3::new(0.0, 0.0, side as f32 * 2.0 * PI / sides as f32)))
+        .collect();
+
+    let mut map = Map::empty();
+    map.name = format!("Generated {}", seed);
+    map.bounds = Some(Bounds::Sphere { radius: params.radius });
+
+    for (side, turn) in turns.iter().enumerate() {
+        let team = if params.team_spawns { Some(side as TeamId + 1) } else { None };
+        let n = params.spawns_per_side as f32;
+        for i in 0..params.spawns_per_side {
+            let along = (i as f32 - (n - 1.0) / 2.0) * SIDE_SPAWN_GAP;
+            let position = *turn * Vector3::new(params.radius * SPAWN_DISTANCE, along, 0.0);
+            map.spawns.push(Spawn { team: team, position: position });
+        }
+    }
+
+    map.wells = wells(params, &turns);
+
+    let volume = 4.0 / 3.0 * PI * params.radius.powi(3);
+    let asteroids = (params.density * volume / 1e6 / sides as f32).round() as usize;
+    for _ in 0..asteroids {
+        for _ in 0..PLACEMENT_TRIES {
+            let position = point_in_sector(&mut rng, params.radius, sides);
+            let radius = asteroid_size(&mut rng, params);
+            let copies: Vec<Vector3<f32>> = turns.iter().map(|turn| *turn * position).collect();
+            if apart(&copies, radius) && copies.iter().all(|copy| clear(&map, *copy, radius, params.radius)) {
+                for copy in copies {
+                    map.obstacles.push(Obstacle::Asteroid { position: copy, radius: radius });
+                }
+                break;
+            }
+        }
+    }
+    map
+}
+
+/// As many wells as split evenly between the sides go in a ring, starting between the first two
+/// sides and turned onto each side the same way as everything else; one more goes in the middle
+/// if there are any left over
+fn wells(params: &Params, turns: &[Rotation3<f32>]) -> Vec<Well> {
+    let well = |position| Well { position: position, strength: params.well_strength, radius: params.well_radius };
+    let sides = turns.len();
+    let per_side = params.wells / sides;
+    let mut wells = Vec::new();
+    if params.wells % sides != 0 {
+        wells.push(well(Vector3::new(0.0, 0.0, 0.0)));
+    }
+    let sector = 2.0 * PI / sides as f32;
+    let distance = params.radius * WELL_RING;
+    for turn in turns {
+        for i in 0..per_side {
+            let angle = sector / 2.0 + i as f32 * sector / per_side as f32;
+            wells.push(well(*turn * Vector3::new(distance * angle.cos(), distance * angle.sin(), 0.0)));
+        }
+    }
+    wells
+}
+
+/// A random point in the arena, in the slice of it that belongs to the first side
+fn point_in_sector<R: Rng>(rng: &mut R, radius: f32, sides: u8) -> Vector3<f32> {
+    loop {
+        let point: Vector3<f32> =
+            Vector3::new(rng.gen_range(-1.0, 1.0), rng.gen_range(-1.0, 1.0), rng.gen_range(-1.0, 1.0));
+        if point.norm() > 1.0 {
+            continue;
+        }
+        let sector = 2.0 * PI / sides as f32;
+        let azimuth = point.y.atan2(point.x);
+        let azimuth = ((azimuth % sector) + sector) % sector;
+        let across = (point.x * point.x + point.y * point.y).sqrt();
+        return Vector3::new(across * azimuth.cos(), across * azimuth.sin(), point.z) * radius;
+    }
+}
+
+/// Squaring a uniform sample skews sizes toward the small end
+fn asteroid_size<R: Rng>(rng: &mut R, params: &Params) -> f32 {
+    let t: f32 = rng.gen();
+    params.min_asteroid + (params.max_asteroid - params.min_asteroid) * t * t
+}
+
+/// Whether the copies of an asteroid, one per side, stay clear of each other
+/// Near the z axis they all bunch up
+fn apart(copies: &[Vector3<f32>], radius: f32) -> bool {
+    copies.iter().enumerate().all(|(i, a)| {
+        copies[i + 1..].iter().all(|b| (*a - *b).norm() > 2.0 * radius)
+    })
+}
+
+/// Whether an asteroid fits at `position` without crowding anything already on the map
+fn clear(map: &Map, position: Vector3<f32>, radius: f32, arena: f32) -> bool {
+    position.norm() + radius <= arena &&
+        map.spawns.iter().all(|spawn| (spawn.position - position).norm() > radius + CLEARANCE) &&
+        map.wells.iter().all(|well| (well.position - position).norm() > radius + well.radius + CLEARANCE) &&
+        map.obstacles.iter().all(|obstacle| match *obstacle {
+            Obstacle::Asteroid { position: other, radius: other_radius } => {
+                (other - position).norm() > radius + other_radius
+            },
+            _ => true,
+        })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn asteroids(map: &Map) -> Vec<(Vector3<f32>, f32)> {
+        map.obstacles.iter().filter_map(|obstacle| match *obstacle {
+            Obstacle::Asteroid { position, radius } => Some((position, radius)),
+            _ => None,
+        }).collect()
+    }
+
+    #[test]
+    fn test_deterministic() {
+        let params = Params::default();
+        assert_eq!(generate(42, &params), generate(42, &params));
+        assert!(generate(42, &params) != generate(43, &params));
+    }
+
+    #[test]
+    fn test_density() {
+        let sparse = Params { density: 1.0, ..Params::default() };
+        let dense = Params { density: 4.0, ..Params::default() };
+        let few = asteroids(&generate(7, &sparse)).len();
+        let many = asteroids(&generate(7, &dense)).len();
+        assert!(few > 0);
+        assert!(many > 2 * few, "{} asteroids at 4x density vs {}", many, few);
+    }
+
+    #[test]
+    fn test_symmetric() {
+        let params = Params { sides: 3, wells: 7, team_spawns: true, ..Params::default() };
+        let map = generate(5, &params);
+        let turn = Rotation3::new(Vector3::new(0.0, 0.0, 2.0 * PI / 3.0));
+        let rocks = asteroids(&map);
+        for &(position, radius) in &rocks {
+            let rotated = turn * position;
+            assert!(rocks.iter().any(|&(other, r)| (other - rotated).norm() < 1e-2 && r == radius),
+                    "every asteroid has a counterpart on the next side");
+        }
+        assert_eq!(6, map.spawns.len());
+        assert_eq!(Some(3), map.spawns[5].team);
+        assert_eq!(7, map.wells.len());
+        assert_eq!(Vector3::new(0.0, 0.0, 0.0), map.wells[0].position);
+        for well in &map.wells {
+            let rotated = turn * well.position;
+            assert!(map.wells.iter().any(|other| (other.position - rotated).norm() < 1e-2),
+                    "every well has a counterpart on the next side");
+        }
+
+        let uneven = generate(5, &Params { sides: 3, wells: 5, ..Params::default() });
+        assert_eq!(4, uneven.wells.len(), "a ring of 3 and one in the middle");
+    }
+
+    #[test]
+    fn test_spawns_are_clear() {
+        let map = generate(11, &Params { density: 8.0, wells: 2, ..Params::default() });
+        for spawn in &map.spawns {
+            for &(position, radius) in &asteroids(&map) {
+                assert!((spawn.position - position).norm() > radius + CLEARANCE);
+            }
+            assert!(map.bounds.as_ref().unwrap().contains(spawn.position));
+        }
+        assert_eq!(None, map.spawns[0].team);
+    }
+}
@@ -1,2 +1,15 @@
+pub mod autopilot;
 pub mod board;
+pub mod boundary;
+pub mod controller;
+pub mod event;
+pub mod gravity;
+pub mod map;
+pub mod mapgen;
+pub mod mode;
+pub mod obstacle;
+pub mod rng;
+pub mod scanner;
+pub mod sensors;
 pub mod ship;
+pub mod targeting;
@@ -0,0 +1,391 @@
+use std::collections::HashMap;
+
+use game::board::{Board, PlayerId, TeamId, Timestep};
+use game::event::Event;
+
+/// Share of the damage teammates do to each other with `FriendlyFire::Reduced`
+const REDUCED_FRIENDLY_FIRE: f32 = 0.5;
+
+/// How a match is scored and won
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub enum GameMode {
+    /// Every ship for itself, a point per kill and a point lost for dying with no one to blame
+    Deathmatch,
+    /// Kills score for the killer's team, killing a teammate costs a point
+    TeamDeathmatch,
+    /// The match ends when only one ship (or one team's ships) is left flying,
+    /// kills only break ties between survivors when time runs out first
+    LastShipStanding,
+}
+
+/// How much damage teammates do to each other
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+pub enum FriendlyFire {
+    Off,
+    Reduced,
+    Full,
+}
+
+impl FriendlyFire {
+    pub fn from_name(name: &str) -> Option<FriendlyFire> {
+        match name {
+            "off" => Some(FriendlyFire::Off),
+            "reduced" => Some(FriendlyFire::Reduced),
+            "full" => Some(FriendlyFire::Full),
+            _ => None,
+        }
+    }
+
+    /// Scale damage done by one teammate to another
+    pub fn scale(&self, damage: f32) -> f32 {
+        match *self {
+            FriendlyFire::Off => 0.0,
+            FriendlyFire::Reduced => damage * REDUCED_FRIENDLY_FIRE,
+            FriendlyFire::Full => damage,
+        }
+    }
+}
+
+/// When a match ends, whichever is reached first
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct Limits {
+    /// Board time in ms
+    pub time: Option<Timestep>,
+    /// Score a player (or team, in team modes) needs to win outright
+    pub score: Option<i32>,
+}
+
+impl Limits {
+    pub fn none() -> Limits {
+        Limits { time: None, score: None }
+    }
+}
+
+/// How a finished match turned out
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct Results {
+    /// Everyone who won, more than one for team wins and ties
+    pub winners: Vec<PlayerId>,
+    /// Winning teams, empty unless the mode has teams
+    pub winning_teams: Vec<TeamId>,
+    /// Every player's score, best first
+    pub scores: Vec<(PlayerId, i32)>,
+    /// Board time the match ended at
+    pub time: Timestep,
+    /// Seed the round was played with, enough to play it over again
+    pub seed: u32,
+}
+
+/// Keeps score for a match and decides when it's over
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct Scoreboard {
+    pub mode: GameMode,
+    pub limits: Limits,
+    /// Seed of the round being scored, for the results
+    pub seed: u32,
+    scores: HashMap<PlayerId, i32>,
+    results: Option<Results>,
+}
+
+impl Scoreboard {
+    pub fn new(mode: GameMode, limits: Limits) -> Scoreboard {
+        Scoreboard {
+            mode: mode,
+            limits: limits,
+            seed: 0,
+            scores: HashMap::new(),
+            results: None,
+        }
+    }
+
+    /// Put `player` on the scoreboard, keeping their score if they're already on it
+    pub fn join(&mut self, player: PlayerId) {
+        self.scores.entry(player).or_insert(0);
+    }
+
+    pub fn score(&self, player: PlayerId) -> i32 {
+        self.scores.get(&player).cloned().unwrap_or(0)
+    }
+
+    pub fn team_score(&self, team: TeamId, board: &Board) -> i32 {
+        self.scores.iter()
+            .filter(|&(player, _)| board.team_of(*player) == Some(team))
+            .map(|(_, score)| score)
+            .sum()
+    }
+
+    /// Every player's score, best first, ties in player order
+    pub fn standings(&self) -> Vec<(PlayerId, i32)> {
+        let mut standings: Vec<(PlayerId, i32)> = self.scores.iter().map(|(p, s)| (*p, *s)).collect();
+        standings.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        standings
+    }
+
+    /// Update scores for something that happened during the match
+    pub fn record(&mut self, event: &Event, board: &Board) {
+        if self.results.is_some() {
+            return;
+        }
+        if let Event::Destroyed { player, by } = *event {
+            self.join(player);
+            match by {
+                Some(killer) if killer != player => {
+                    let points = if board.teammates(killer, player) { -1 } else { 1 };
+                    *self.scores.entry(killer).or_insert(0) += points;
+                },
+                _ => {
+                    if self.mode != GameMode::LastShipStanding {
+                        *self.scores.entry(player).or_insert(0) -= 1;
+                    }
+                },
+            }
+        }
+    }
+
+    /// End the match if any of its end conditions have been met, returns the results if it's over
+    pub fn check(&mut self, board: &Board) -> Option<&Results> {
+        if self.results.is_none() && self.over(board) {
+            self.results = Some(self.tally(board));
+        }
+        self.results.as_ref()
+    }
+
+    pub fn results(&self) -> Option<&Results> {
+        self.results.as_ref()
+    }
+
+    fn over(&self, board: &Board) -> bool {
+        if self.limits.time.map_or(false, |limit| board.time() >= limit) {
+            return true;
+        }
+        match self.mode {
+            GameMode::Deathmatch => {
+                self.limits.score.map_or(false, |limit| self.scores.values().any(|s| *s >= limit))
+            },
+            GameMode::TeamDeathmatch => {
+                self.limits.score.map_or(false, |limit| {
+                    self.teams(board).iter().any(|team| self.team_score(*team, board) >= limit)
+                })
+            },
+            GameMode::LastShipStanding => {
+                self.scores.len() >= 2 && one_side_left(&self.survivors(board), board)
+            },
+        }
+    }
+
+    fn tally(&self, board: &Board) -> Results {
+        let scores = self.standings();
+        let mut winning_teams = Vec::new();
+        let winners = match self.mode {
+            GameMode::Deathmatch => {
+                let best = scores.first().map_or(0, |&(_, score)| score);
+                scores.iter().filter(|&&(_, score)| score == best).map(|&(player, _)| player).collect()
+            },
+            GameMode::TeamDeathmatch => {
+                let teams = self.teams(board);
+                let best = teams.iter().map(|team| self.team_score(*team, board)).max().unwrap_or(0);
+                winning_teams = teams.into_iter()
+                    .filter(|team| self.team_score(*team, board) == best)
+                    .collect();
+                scores.iter()
+                    .map(|&(player, _)| player)
+                    .filter(|player| board.team_of(*player).map_or(false, |team| winning_teams.contains(&team)))
+                    .collect()
+            },
+            GameMode::LastShipStanding => {
+                let survivors = self.survivors(board);
+                // out of time with more than one side still flying, the most kills wins
+                let winners: Vec<PlayerId> = if one_side_left(&survivors, board) {
+                    survivors
+                } else {
+                    let best = survivors.iter().map(|p| self.score(*p)).max().unwrap_or(0);
+                    survivors.into_iter().filter(|p| self.score(*p) == best).collect()
+                };
+                winning_teams = winners.iter().filter_map(|p| board.team_of(*p)).collect();
+                winning_teams.sort();
+                winning_teams.dedup();
+                winners
+            },
+        };
+        Results {
+            winners: winners,
+            winning_teams: winning_teams,
+            scores: scores,
+            time: board.time(),
+            seed: self.seed,
+        }
+    }
+
+    /// Teams with at least one player on the scoreboard, in order
+    fn teams(&self, board: &Board) -> Vec<TeamId> {
+        let mut teams: Vec<TeamId> = self.scores.keys().filter_map(|player| board.team_of(*player)).collect();
+        teams.sort();
+        teams.dedup();
+        teams
+    }
+
+    /// Players on the scoreboard who still have a ship, in order
+    fn survivors(&self, board: &Board) -> Vec<PlayerId> {
+        let mut survivors: Vec<PlayerId> = self.scores.keys()
+            .filter(|player| board.ships.contains_key(player))
+            .cloned()
+            .collect();
+        survivors.sort();
+        survivors
+    }
+}
+
+/// Whether `survivors` are all on one side: a lone ship, nobody at all, or a single team
+fn one_side_left(survivors: &[PlayerId], board: &Board) -> bool {
+    let mut sides: Vec<Option<TeamId>> = survivors.iter().map(|p| board.team_of(*p)).collect();
+    sides.dedup();
+    survivors.len() <= 1 || (sides.len() == 1 && sides[0].is_some())
+}
+
+#[cfg(test)]
+mod test {
+    use game::board::Team;
+    use game::ship::Ship;
+    use super::*;
+
+    fn board(players: &[PlayerId]) -> Board {
+        let mut board = Board::new();
+        for player in players {
+            board.add_ship(*player, Ship::at_origin());
+        }
+        board
+    }
+
+    /// Two teams, players 1 and 2 on one and 3 and 4 on the other
+    fn teams(players: &[PlayerId]) -> Board {
+        let mut board = board(players);
+        for (id, team) in Team::defaults(2) {
+            board.add_team(id, team);
+        }
+        for player in 1..5 {
+            board.join_team(player, Some((player + 1) / 2));
+        }
+        board
+    }
+
+    fn kill(scoreboard: &mut Scoreboard, player: PlayerId, by: Option<PlayerId>) {
+        scoreboard.record(&Event::Destroyed { player: player, by: by }, &Board::new());
+    }
+
+    fn team_kill(scoreboard: &mut Scoreboard, player: PlayerId, by: PlayerId) {
+        scoreboard.record(&Event::Destroyed { player: player, by: Some(by) }, &teams(&[]));
+    }
+
+    #[test]
+    fn test_deathmatch_scoring() {
+        let mut scoreboard = Scoreboard::new(GameMode::Deathmatch, Limits::none());
+        for player in 1..4 {
+            scoreboard.join(player);
+        }
+        kill(&mut scoreboard, 2, Some(1));
+        kill(&mut scoreboard, 3, Some(1));
+        kill(&mut scoreboard, 3, None);
+        assert_eq!(2, scoreboard.score(1));
+        assert_eq!(0, scoreboard.score(2));
+        assert_eq!(-1, scoreboard.score(3));
+        assert_eq!(vec![(1, 2), (2, 0), (3, -1)], scoreboard.standings());
+        assert!(scoreboard.check(&board(&[1, 2, 3])).is_none(), "no limits, never over");
+    }
+
+    #[test]
+    fn test_score_limit() {
+        let mut scoreboard = Scoreboard::new(GameMode::Deathmatch, Limits { time: None, score: Some(2) });
+        scoreboard.join(1);
+        scoreboard.join(2);
+        kill(&mut scoreboard, 2, Some(1));
+        assert!(scoreboard.check(&board(&[1, 2])).is_none());
+        kill(&mut scoreboard, 2, Some(1));
+        let results = scoreboard.check(&board(&[1, 2])).expect("score limit reached").clone();
+        assert_eq!(vec![1], results.winners);
+
+        kill(&mut scoreboard, 1, Some(2));
+        assert_eq!(0, scoreboard.score(2), "no scoring after the match is over");
+    }
+
+    #[test]
+    fn test_time_limit_tie() {
+        let mut scoreboard = Scoreboard::new(GameMode::Deathmatch, Limits { time: Some(1000), score: None });
+        scoreboard.join(1);
+        scoreboard.join(2);
+        let mut board = board(&[1, 2]);
+        board.advance(990);
+        assert!(scoreboard.check(&board).is_none());
+        board.advance(10);
+        let results = scoreboard.check(&board).expect("out of time");
+        assert_eq!(vec![1, 2], results.winners);
+        assert_eq!(1000, results.time);
+    }
+
+    #[test]
+    fn test_team_deathmatch() {
+        let mut scoreboard = Scoreboard::new(GameMode::TeamDeathmatch, Limits { time: None, score: Some(2) });
+        for player in 1..5 {
+            scoreboard.join(player);
+        }
+        team_kill(&mut scoreboard, 3, 1);
+        team_kill(&mut scoreboard, 2, 1);
+        assert_eq!(0, scoreboard.score(1), "teamkills cost a point");
+        team_kill(&mut scoreboard, 4, 2);
+        team_kill(&mut scoreboard, 3, 2);
+        let board = teams(&[1, 2, 3, 4]);
+        assert_eq!(2, scoreboard.team_score(1, &board));
+        let results = scoreboard.check(&board).expect("team score limit");
+        assert_eq!(vec![1], results.winning_teams);
+        assert_eq!(vec![2, 1], results.winners);
+    }
+
+    #[test]
+    fn test_last_team_standing() {
+        let mut scoreboard = Scoreboard::new(GameMode::LastShipStanding, Limits::none());
+        for player in 1..5 {
+            scoreboard.join(player);
+        }
+        assert!(scoreboard.check(&teams(&[1, 3])).is_none(), "both teams still flying");
+        let results = scoreboard.check(&teams(&[3, 4])).expect("one team left");
+        assert_eq!(vec![3, 4], results.winners);
+        assert_eq!(vec![2], results.winning_teams);
+    }
+
+    #[test]
+    fn test_friendly_fire() {
+        assert_eq!(0.0, FriendlyFire::Off.scale(10.0));
+        assert_eq!(5.0, FriendlyFire::Reduced.scale(10.0));
+        assert_eq!(10.0, FriendlyFire::Full.scale(10.0));
+        assert_eq!(Some(FriendlyFire::Reduced), FriendlyFire::from_name("reduced"));
+        assert_eq!(None, FriendlyFire::from_name("some"));
+    }
+
+    #[test]
+    fn test_last_ship_standing() {
+        let mut scoreboard = Scoreboard::new(GameMode::LastShipStanding, Limits::none());
+        scoreboard.join(1);
+        assert!(scoreboard.check(&board(&[1])).is_none(), "a lone ship hasn't won anything yet");
+        scoreboard.join(2);
+        scoreboard.join(3);
+        kill(&mut scoreboard, 2, Some(3));
+        kill(&mut scoreboard, 1, None);
+        assert_eq!(0, scoreboard.score(1), "no penalty for dying");
+        assert!(scoreboard.check(&board(&[1, 3])).is_none());
+        let results = scoreboard.check(&board(&[3])).expect("one ship left");
+        assert_eq!(vec![3], results.winners);
+        assert_eq!((3, 1), results.scores[0]);
+    }
+
+    #[test]
+    fn test_last_ship_standing_out_of_time() {
+        let mut scoreboard = Scoreboard::new(GameMode::LastShipStanding, Limits { time: Some(1000), score: None });
+        for player in 1..4 {
+            scoreboard.join(player);
+        }
+        kill(&mut scoreboard, 3, Some(2));
+        let mut board = board(&[1, 2]);
+        board.advance(1000);
+        let results = scoreboard.check(&board).expect("out of time");
+        assert_eq!(vec![2], results.winners, "the survivor with more kills");
+    }
+}
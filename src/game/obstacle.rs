@@ -0,0 +1,20 @@
+use na::Vector3;
+
+/// Static scenery that ships collide with and can't see or shoot through
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub enum Obstacle {
+    Asteroid { position: Vector3<f32>, radius: f32 },
+    Block { position: Vector3<f32>, half_extents: Vector3<f32> },
+    /// A cylinder standing along the y axis
+    Station { position: Vector3<f32>, radius: f32, half_height: f32 },
+}
+
+impl Obstacle {
+    pub fn position(&self) -> Vector3<f32> {
+        match *self {
+            Obstacle::Asteroid { position, .. } => position,
+            Obstacle::Block { position, .. } => position,
+            Obstacle::Station { position, .. } => position,
+        }
+    }
+}
@@ -0,0 +1,108 @@
+use rand::Rng;
+
+/// The only source of randomness in the simulation, seeded so any match can be played again
+///
+/// A plain xorshift128 generator, with its state out in the open so it can be saved and
+/// restored along with everything else in a round.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct GameRng {
+    seed: u32,
+    state: [u32; 4],
+}
+
+/// Spread the bits of `x` around, used to turn seeds and keys into generator state
+fn splitmix(x: &mut u64) -> u64 {
+    *x = x.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    let mut z = *x;
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+impl GameRng {
+    pub fn new(seed: u32) -> GameRng {
+        let mut mix = seed as u64;
+        GameRng {
+            seed: seed,
+            state: GameRng::fill(&mut mix),
+        }
+    }
+
+    fn fill(mix: &mut u64) -> [u32; 4] {
+        let (a, b) = (splitmix(mix), splitmix(mix));
+        let state = [a as u32, (a >> 32) as u32, b as u32, (b >> 32) as u32];
+        // all zeroes is the one state xorshift can't get out of
+        if state == [0, 0, 0, 0] { [1, 0, 0, 0] } else { state }
+    }
+
+    /// The seed the generator started from
+    pub fn seed(&self) -> u32 {
+        self.seed
+    }
+
+    /// Where the generator has got to
+    pub fn state(&self) -> [u32; 4] {
+        self.state
+    }
+
+    /// A separate generator for `key`, without moving this one along
+    /// The same state and key always give the same stream, however many times it's asked for.
+    pub fn fork(&self, key: u64) -> GameRng {
+        let mut mix = (self.state[0] as u64 | (self.state[1] as u64) << 32) ^
+                      (self.state[2] as u64 | (self.state[3] as u64) << 32).rotate_left(17) ^
+                      key.wrapping_mul(0xd6e8_feb8_6659_fd93);
+        GameRng {
+            seed: self.seed,
+            state: GameRng::fill(&mut mix),
+        }
+    }
+}
+
+impl Rng for GameRng {
+    fn next_u32(&mut self) -> u32 {
+        let t = self.state[0] ^ (self.state[0] << 11);
+        self.state[0] = self.state[1];
+        self.state[1] = self.state[2];
+        self.state[2] = self.state[3];
+        self.state[3] = self.state[3] ^ (self.state[3] >> 19) ^ (t ^ (t >> 8));
+        self.state[3]
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use bincode::{serialize, deserialize, Infinite};
+    use super::*;
+
+    fn draws(rng: &mut GameRng) -> Vec<u32> {
+        (0..8).map(|_| rng.next_u32()).collect()
+    }
+
+    #[test]
+    fn test_seeded() {
+        assert_eq!(draws(&mut GameRng::new(7)), draws(&mut GameRng::new(7)));
+        assert!(draws(&mut GameRng::new(7)) != draws(&mut GameRng::new(8)));
+        assert!(draws(&mut GameRng::new(0)).iter().any(|x| *x != 0), "zero is a fine seed");
+        assert_eq!(7, GameRng::new(7).seed());
+    }
+
+    #[test]
+    fn test_fork() {
+        let rng = GameRng::new(3);
+        assert_eq!(draws(&mut rng.fork(1)), draws(&mut rng.fork(1)));
+        assert!(draws(&mut rng.fork(1)) != draws(&mut rng.fork(2)));
+        assert_eq!(GameRng::new(3), rng, "forking doesn't move the parent along");
+
+        let mut moved = rng.clone();
+        moved.next_u32();
+        assert!(draws(&mut rng.fork(1)) != draws(&mut moved.fork(1)));
+    }
+
+    #[test]
+    fn test_serialize() {
+        let mut rng = GameRng::new(11);
+        rng.next_u32();
+        let mut decoded: GameRng = deserialize(&serialize(&rng, Infinite).unwrap()).unwrap();
+        assert_eq!(draws(&mut rng), draws(&mut decoded), "picks up right where it left off");
+    }
+}
@@ -0,0 +1,162 @@
+use std::rc::Rc;
+
+use na::Vector3;
+
+use game::board::PlayerId;
+use game::controller::{Contact, SensorView};
+use game::ship::SHIP_RADIUS;
+
+/// What a ray cast through the world ran into
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum RayHit {
+    Ship(PlayerId),
+    Obstacle,
+}
+
+/// The scenery a scanner casts rays against, ships are left to the sensors
+pub trait Terrain {
+    /// How far along a ray from `origin` the first obstacle is, if there's one within `max_dist`
+    fn raycast_obstacles(&self, origin: Vector3<f32>, dir: Vector3<f32>, max_dist: f32) -> Option<f32>;
+}
+
+/// Spatial queries from one ship's point of view, limited to what its sensors picked up
+/// Ships are only ever found where the ship's sensor view puts them, noise and all
+#[derive(Clone)]
+pub struct Scanner {
+    terrain: Rc<Terrain>,
+    position: Vector3<f32>,
+    contacts: Vec<Contact>,
+    range: f32,
+}
+
+impl Scanner {
+    pub fn new(terrain: Rc<Terrain>, view: &SensorView, range: f32) -> Scanner {
+        Scanner {
+            terrain: terrain,
+            position: view.position,
+            contacts: view.contacts.clone(),
+            range: range,
+        }
+    }
+
+    /// Contacts within `radius` of this ship (capped at sensor range), nearest first
+    pub fn ships_within(&self, radius: f32) -> Vec<(PlayerId, f32)> {
+        let radius = radius.min(self.range);
+        let mut found: Vec<(PlayerId, f32)> = self.contacts.iter()
+            .map(|contact| (contact.player, (contact.position - self.position).norm()))
+            .filter(|&(_, dist)| dist <= radius)
+            .collect();
+        found.sort_by(|a, b| a.1.partial_cmp(&b.1).expect("distances aren't NaN"));
+        found
+    }
+
+    /// The closest contact
+    pub fn nearest(&self) -> Option<(PlayerId, f32)> {
+        self.ships_within(self.range).into_iter().next()
+    }
+
+    /// What a ray from this ship along `dir` hits first, within `max_dist` and sensor range
+    pub fn raycast(&self, dir: Vector3<f32>, max_dist: f32) -> Option<(RayHit, f32)> {
+        let norm = dir.norm();
+        if norm == 0.0 || !norm.is_finite() {
+            return None;
+        }
+        let dir = dir / norm;
+        let max_dist = max_dist.min(self.range);
+        let obstacle = self.terrain.raycast_obstacles(self.position, dir, max_dist)
+            .map(|dist| (RayHit::Obstacle, dist));
+        self.contacts.iter()
+            .filter_map(|contact| {
+                ray_sphere(self.position, dir, contact.position, SHIP_RADIUS)
+                    .map(|dist| (RayHit::Ship(contact.player), dist))
+            })
+            .filter(|&(_, dist)| dist <= max_dist)
+            .chain(obstacle)
+            .fold(None, |closest, (hit, dist)| {
+                match closest {
+                    Some((_, best)) if best <= dist => closest,
+                    _ => Some((hit, dist)),
+                }
+            })
+    }
+}
+
+/// How far along a ray from `origin` in the unit direction `dir` it first touches a ball,
+/// 0 if it starts inside
+pub fn ray_sphere(origin: Vector3<f32>, dir: Vector3<f32>, center: Vector3<f32>, radius: f32) -> Option<f32> {
+    let to_center = center - origin;
+    let along = to_center.dot(&dir);
+    let miss_sq = to_center.norm_squared() - along * along;
+    if miss_sq > radius * radius {
+        return None;
+    }
+    let half_chord = (radius * radius - miss_sq).sqrt();
+    if along + half_chord < 0.0 {
+        None
+    } else {
+        Some((along - half_chord).max(0.0))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use game::board::Board;
+    use game::ship::Ship;
+    use super::*;
+
+    /// A single asteroid
+    struct Asteroid(Vector3<f32>, f32);
+
+    impl Terrain for Asteroid {
+        fn raycast_obstacles(&self, origin: Vector3<f32>, dir: Vector3<f32>, max_dist: f32) -> Option<f32> {
+            ray_sphere(origin, dir, self.0, self.1).and_then(|dist| if dist <= max_dist { Some(dist) } else { None })
+        }
+    }
+
+    fn scanner(range: f32) -> Scanner {
+        let mut board = Board::new();
+        board.add_ship(1, Ship::at_origin());
+        board.add_ship(2, Ship::at(Vector3::new(10.0, 0.0, 0.0)));
+        board.add_ship(3, Ship::at(Vector3::new(0.0, -30.0, 0.0)));
+        board.add_ship(4, Ship::at(Vector3::new(0.0, 50.0, 0.0))); // hidden behind the asteroid
+        let mut view = SensorView::from_board(&board, 1).unwrap();
+        view.contacts.retain(|contact| contact.player != 4);
+        Scanner::new(Rc::new(Asteroid(Vector3::new(0.0, 25.0, 0.0), 2.0)), &view, range)
+    }
+
+    #[test]
+    fn test_ships_within() {
+        let seen: Vec<PlayerId> = scanner(60.0).ships_within(100.0).into_iter().map(|(p, _)| p).collect();
+        assert_eq!(vec![2, 3], seen, "ship 4 isn't in the view");
+        assert_eq!(Some(2), scanner(60.0).nearest().map(|(p, _)| p));
+        assert_eq!(1, scanner(20.0).ships_within(100.0).len());
+    }
+
+    #[test]
+    fn test_raycast() {
+        let up = Vector3::new(0.0, 1.0, 0.0);
+        let (hit, dist) = scanner(60.0).raycast(up, 100.0).expect("hit");
+        assert_eq!(RayHit::Obstacle, hit);
+        assert!((dist - 23.0).abs() < 0.01, "hit the asteroid's surface: {}", dist);
+        assert!(scanner(20.0).raycast(up, 100.0).is_none());
+
+        let (hit, dist) = scanner(60.0).raycast(Vector3::new(2.0, 0.0, 0.0), 100.0).expect("hit");
+        assert_eq!(RayHit::Ship(2), hit);
+        assert!((dist - 9.5).abs() < 0.01);
+        assert!(scanner(60.0).raycast(Vector3::new(1.0, 0.0, 0.0), 5.0).is_none());
+        assert!(scanner(60.0).raycast(Vector3::new(0.0, 0.0, 0.0), 100.0).is_none());
+    }
+
+    #[test]
+    fn test_noisy_contacts() {
+        let mut board = Board::new();
+        board.add_ship(1, Ship::at_origin());
+        board.add_ship(2, Ship::at(Vector3::new(10.0, 0.0, 0.0)));
+        let mut view = SensorView::from_board(&board, 1).unwrap();
+        view.contacts[0].position = Vector3::new(0.0, 0.0, 20.0); // where the sensors think it is
+        let scanner = Scanner::new(Rc::new(Asteroid(Vector3::new(0.0, 100.0, 0.0), 1.0)), &view, 100.0);
+        assert!(scanner.raycast(Vector3::new(1.0, 0.0, 0.0), 50.0).is_none());
+        assert_eq!(Some(RayHit::Ship(2)), scanner.raycast(Vector3::new(0.0, 0.0, 1.0), 50.0).map(|(hit, _)| hit));
+        assert_eq!(vec![(2, 20.0)], scanner.ships_within(30.0));
+    }
+}
@@ -0,0 +1,158 @@
+use na::Vector3;
+use rand::Rng;
+
+use game::board::{Board, PlayerId};
+use game::boundary;
+use game::controller::{Contact, SensorView};
+
+/// How well a ship can see the rest of the board
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct SensorModel {
+    /// Ships further away than this aren't detected at all
+    pub range: f32,
+    /// Contacts closer than this are reported exactly
+    pub exact_range: f32,
+    /// Beyond `exact_range`, position and velocity errors grow by this much per unit distance
+    pub noise_per_unit: f32,
+    /// In an arena that wraps around, the half extents of its box
+    /// Contacts are then reported wherever they're closest, which may be across an edge
+    pub wrap: Option<Vector3<f32>>,
+}
+
+impl SensorModel {
+    pub fn new() -> SensorModel {
+        SensorModel {
+            range: 150.0,
+            exact_range: 50.0,
+            noise_per_unit: 0.05,
+            wrap: None,
+        }
+    }
+
+    /// Perfect sensors: every ship on the board, exactly where it is
+    pub fn omniscient() -> SensorModel {
+        SensorModel {
+            range: ::std::f32::INFINITY,
+            exact_range: ::std::f32::INFINITY,
+            noise_per_unit: 0.0,
+            wrap: None,
+        }
+    }
+
+    /// What `player`'s sensors pick up, or None if they don't have a ship on the board
+    /// Ships that `visible` says are hidden from `player` are never detected
+    pub fn view<R, F>(&self, board: &Board, player: PlayerId, rng: &mut R, visible: F) -> Option<SensorView>
+        where R: Rng, F: Fn(PlayerId) -> bool
+    {
+        SensorView::from_board(board, player).map(|mut view| {
+            let own_position = view.position;
+            view.contacts = view.contacts.into_iter()
+                .filter(|contact| visible(contact.player))
+                .filter_map(|contact| self.detect(own_position, contact, rng))
+                .collect();
+            view
+        })
+    }
+
+    fn detect<R: Rng>(&self, from: Vector3<f32>, mut contact: Contact, rng: &mut R) -> Option<Contact> {
+        if let Some(half_extents) = self.wrap {
+            contact.position = from + boundary::offset(from, contact.position, half_extents);
+        }
+        let distance = (contact.position - from).norm();
+        if distance > self.range {
+            return None;
+        }
+        if distance <= self.exact_range {
+            return Some(contact);
+        }
+        let error = (distance - self.exact_range) * self.noise_per_unit;
+        Some(Contact {
+            player: contact.player,
+            position: contact.position + jitter(rng) * error,
+            velocity: contact.velocity + jitter(rng) * error,
+            team: contact.team,
+        })
+    }
+}
+
+/// A random vector with each component in [-1, 1)
+fn jitter<R: Rng>(rng: &mut R) -> Vector3<f32> {
+    Vector3::new(rng.gen_range(-1.0, 1.0), rng.gen_range(-1.0, 1.0), rng.gen_range(-1.0, 1.0))
+}
+
+#[cfg(test)]
+mod test {
+    use rand::{SeedableRng, XorShiftRng};
+    use game::ship::Ship;
+    use super::*;
+
+    fn board() -> Board {
+        let mut board = Board::new();
+        board.add_ship(1, Ship::at_origin());
+        board.add_ship(2, Ship::at(Vector3::new(10.0, 0.0, 0.0)));
+        board.add_ship(3, Ship::at(Vector3::new(0.0, 100.0, 0.0)));
+        board.add_ship(4, Ship::at(Vector3::new(0.0, 0.0, 500.0)));
+        board
+    }
+
+    fn contact(view: &SensorView, player: PlayerId) -> Option<&Contact> {
+        view.contacts.iter().find(|c| c.player == player)
+    }
+
+    #[test]
+    fn test_range() {
+        let mut rng = XorShiftRng::from_seed([1, 2, 3, 4]);
+        let view = SensorModel::new().view(&board(), 1, &mut rng, |_| true).expect("player 1 has a ship");
+        assert_eq!(2, view.contacts.len());
+        assert!(contact(&view, 4).is_none(), "ship 4 is out of range");
+        assert!(SensorModel::new().view(&board(), 9, &mut rng, |_| true).is_none());
+    }
+
+    #[test]
+    fn test_hidden() {
+        let mut rng = XorShiftRng::from_seed([1, 2, 3, 4]);
+        let view = SensorModel::new().view(&board(), 1, &mut rng, |other| other != 2).unwrap();
+        assert!(contact(&view, 2).is_none(), "ship 2 is hidden");
+        assert!(contact(&view, 3).is_some());
+    }
+
+    #[test]
+    fn test_noise() {
+        let mut rng = XorShiftRng::from_seed([1, 2, 3, 4]);
+        let model = SensorModel::new();
+        let view = model.view(&board(), 1, &mut rng, |_| true).unwrap();
+
+        let close = contact(&view, 2).expect("ship 2 is visible");
+        assert_eq!(Vector3::new(10.0, 0.0, 0.0), close.position);
+
+        let far = contact(&view, 3).expect("ship 3 is visible");
+        let max_error = (100.0 - model.exact_range) * model.noise_per_unit;
+        let error = far.position - Vector3::new(0.0, 100.0, 0.0);
+        assert!(error.norm() > 0.0, "distant contacts are fuzzy");
+        assert!(error.x.abs() <= max_error && error.y.abs() <= max_error && error.z.abs() <= max_error);
+    }
+
+    #[test]
+    fn test_wrap() {
+        let mut rng = XorShiftRng::from_seed([1, 2, 3, 4]);
+        let mut board = Board::new();
+        board.add_ship(1, Ship::at(Vector3::new(95.0, 0.0, 0.0)));
+        board.add_ship(2, Ship::at(Vector3::new(-95.0, 0.0, 0.0)));
+        let mut model = SensorModel::new();
+        model.range = 50.0;
+        assert!(model.view(&board, 1, &mut rng, |_| true).unwrap().contacts.is_empty());
+
+        model.wrap = Some(Vector3::new(100.0, 100.0, 100.0));
+        let view = model.view(&board, 1, &mut rng, |_| true).unwrap();
+        let across = contact(&view, 2).expect("seen across the edge");
+        assert_eq!(Vector3::new(105.0, 0.0, 0.0), across.position);
+    }
+
+    #[test]
+    fn test_omniscient() {
+        let mut rng = XorShiftRng::from_seed([1, 2, 3, 4]);
+        let board = board();
+        let view = SensorModel::omniscient().view(&board, 1, &mut rng, |_| true).unwrap();
+        assert_eq!(SensorView::from_board(&board, 1), Some(view));
+    }
+}
@@ -1,10 +1,24 @@
+use std::f32::consts::PI;
 use na::{Vector3, Rotation3, Translation3};
 
-#[derive(Serialize, Deserialize, PartialEq, Debug)]
+pub const SHIP_RADIUS: f32 = 0.5;
+pub const MAX_HULL: f32 = 100.0;
+pub const MAX_THRUST: f32 = 10.0; // newtons
+pub const MAX_TORQUE: f32 = 2.0;
+pub const SHIP_DENSITY: f32 = 1.0;
+
+/// Mass of a ship's physics body, a ball of `SHIP_RADIUS` at `SHIP_DENSITY`
+pub fn ship_mass() -> f32 {
+    SHIP_DENSITY * 4.0 / 3.0 * PI * SHIP_RADIUS.powi(3)
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
 pub struct Ship {
     position: Vector3<f32>,
     orientation: Rotation3<f32>,
     velocity: Vector3<f32>,
+    spin: Vector3<f32>,
+    hull: f32,
 }
 
 impl Ship {
@@ -13,6 +27,18 @@ impl Ship {
             position: Vector3::new(0.0, 0.0, 0.0),
             orientation: Rotation3::identity(),
             velocity: Vector3::new(1.0, 0.0, 0.0),
+            spin: Vector3::new(0.0, 0.0, 0.0),
+            hull: MAX_HULL,
+        }
+    }
+
+    pub fn at(position: Vector3<f32>) -> Ship {
+        Ship {
+            position: position,
+            orientation: Rotation3::identity(),
+            velocity: Vector3::new(0.0, 0.0, 0.0),
+            spin: Vector3::new(0.0, 0.0, 0.0),
+            hull: MAX_HULL,
         }
     }
 
@@ -20,13 +46,54 @@ impl Ship {
         let pos = self.position;
         Translation3::new(pos.x, pos.y, pos.z)
     }
+
+    pub fn position(&self) -> Vector3<f32> {
+        self.position
+    }
+
+    pub fn orientation(&self) -> Rotation3<f32> {
+        self.orientation
+    }
+
+    pub fn velocity(&self) -> Vector3<f32> {
+        self.velocity
+    }
+
+    /// Angular velocity, the axis the ship is turning around scaled by radians per second
+    pub fn spin(&self) -> Vector3<f32> {
+        self.spin
+    }
+
+    /// Unit vector pointing out of the ship's nose, which is where its weapons fire
+    pub fn heading(&self) -> Vector3<f32> {
+        self.orientation * Vector3::new(1.0, 0.0, 0.0)
+    }
+
+    pub fn hull(&self) -> f32 {
+        self.hull
+    }
+
+    /// Overwrite the ship's kinematic state, usually with the results of a physics step
+    pub fn update(&mut self, position: Vector3<f32>, orientation: Rotation3<f32>,
+                  velocity: Vector3<f32>, spin: Vector3<f32>) {
+        self.position = position;
+        self.orientation = orientation;
+        self.velocity = velocity;
+        self.spin = spin;
+    }
+
+    /// Apply damage to the hull, returns true if the ship was destroyed
+    pub fn damage(&mut self, amount: f32) -> bool {
+        self.hull -= amount;
+        self.hull <= 0.0
+    }
 }
 
 #[cfg(test)]
 mod test {
     use na::{Vector3, Rotation3};
     use bincode::{serialize, deserialize, Infinite};
-    use super::Ship;
+    use super::*;
 
     #[test]
     fn serialization() {
@@ -34,13 +101,29 @@ mod test {
             position: Vector3::new(0.0, 1.0, 0.0),
             orientation: Rotation3::identity(),
             velocity: Vector3::new(1.0, 0.0, 0.0),
+            spin: Vector3::new(0.0, 0.0, 0.0),
+            hull: MAX_HULL,
         };
         let encoded: Vec<u8> = serialize(&ship, Infinite).unwrap();
 
-        assert_eq!(encoded.len(), 60); // TODO: why is this 60 bytes?
+        assert_eq!(encoded.len(), 76); // 19 f32s: 3 position, 9 rotation, 3 velocity, 3 spin, hull
 
         let decoded: Ship = deserialize(&encoded[..]).unwrap();
 
         assert_eq!(ship, decoded);
     }
+
+    #[test]
+    fn test_damage() {
+        let mut ship = Ship::at_origin();
+        assert!(!ship.damage(MAX_HULL / 2.0));
+        assert_eq!(MAX_HULL / 2.0, ship.hull());
+        assert!(ship.damage(MAX_HULL / 2.0));
+    }
+
+    #[test]
+    fn test_heading() {
+        let ship = Ship::at_origin();
+        assert_eq!(Vector3::new(1.0, 0.0, 0.0), ship.heading());
+    }
 }
@@ -0,0 +1,170 @@
+use na::{Rotation3, Vector3};
+
+use game::controller::clamp_norm;
+use game::ship::{Ship, MAX_TORQUE};
+
+/// How fast to turn toward the aim point, radians per second for every radian off target
+const TURN_GAIN: f32 = 4.0;
+/// Torque applied per radian per second of difference between the spin we have and the spin
+/// we want, roughly a ship's moment of inertia over the time we give it to catch up
+const SPIN_RESPONSE: f32 = 0.5;
+
+/// Where to shoot to hit a moving target
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Solution {
+    /// Unit vector to fire along, relative to the shooter's own motion
+    pub aim: Vector3<f32>,
+    /// Where the target will be when the shot arrives
+    pub point: Vector3<f32>,
+    /// Seconds until impact
+    pub time: f32,
+}
+
+/// Lead `target` with a projectile leaving `shooter` at `speed`
+/// Projectiles inherit the shooter's velocity, so only relative motion matters.
+/// Returns None when the target is outrunning the shot.
+pub fn intercept(shooter: &Ship, target: &Ship, speed: f32) -> Option<Solution> {
+    lead(shooter.position(), shooter.velocity(), target.position(), target.velocity(), speed)
+}
+
+/// `intercept` for anything with a position and velocity, e.g. a sensor contact
+pub fn lead(from: Vector3<f32>, from_velocity: Vector3<f32>,
+            to: Vector3<f32>, to_velocity: Vector3<f32>, speed: f32) -> Option<Solution> {
+    let offset = to - from;
+    let drift = to_velocity - from_velocity;
+
+    // solve |offset + drift * t| = speed * t for the earliest t > 0
+    let a = drift.dot(&drift) - speed * speed;
+    let b = 2.0 * offset.dot(&drift);
+    let c = offset.dot(&offset);
+    let time = if a.abs() < 1e-6 {
+        if b >= 0.0 { return None; }
+        -c / b
+    } else {
+        let discriminant = b * b - 4.0 * a * c;
+        if discriminant < 0.0 {
+            return None;
+        }
+        let root = discriminant.sqrt();
+        let early = (-b - root) / (2.0 * a);
+        let late = (-b + root) / (2.0 * a);
+        match (early.min(late), early.max(late)) {
+            (t, _) if t > 0.0 => t,
+            (_, t) if t > 0.0 => t,
+            _ => return None,
+        }
+    };
+
+    let relative = offset + drift * time;
+    if relative.norm() == 0.0 {
+        return None;
+    }
+    Some(Solution {
+        aim: relative.normalize(),
+        point: to + to_velocity * time,
+        time: time,
+    })
+}
+
+/// Torque that swings `ship`'s nose toward `aim`, slowing down on the way in so it doesn't
+/// overshoot, within what its engines can deliver
+pub fn turn_toward(ship: &Ship, aim: Vector3<f32>) -> Vector3<f32> {
+    steer(ship.orientation(), ship.spin(), aim)
+}
+
+/// `turn_toward` for anything with an orientation and spin, e.g. a controller's own view
+pub fn steer(orientation: Rotation3<f32>, spin: Vector3<f32>, aim: Vector3<f32>) -> Vector3<f32> {
+    let norm = aim.norm();
+    if norm == 0.0 {
+        return clamp_norm(-spin * SPIN_RESPONSE, MAX_TORQUE);
+    }
+    let heading = orientation * Vector3::new(1.0, 0.0, 0.0);
+    let aim = aim / norm;
+
+    let cross = heading.cross(&aim);
+    let angle = cross.norm().atan2(heading.dot(&aim));
+    let axis = if cross.norm() > 1e-6 {
+        cross.normalize()
+    } else if heading.dot(&aim) < 0.0 {
+        // pointing straight away, any axis at right angles to the nose will do
+        orientation * Vector3::new(0.0, 0.0, 1.0)
+    } else {
+        Vector3::new(0.0, 0.0, 0.0)
+    };
+
+    let wanted = axis * (angle * TURN_GAIN);
+    clamp_norm((wanted - spin) * SPIN_RESPONSE, MAX_TORQUE)
+}
+
+#[cfg(test)]
+mod test {
+    use std::f32::consts::FRAC_PI_2;
+    use na::{Rotation3, Vector3};
+    use game::ship::Ship;
+    use super::*;
+
+    fn close(a: Vector3<f32>, b: Vector3<f32>) -> bool {
+        (a - b).norm() < 1e-4
+    }
+
+    #[test]
+    fn test_stationary_target() {
+        let shooter = Ship::at(Vector3::new(0.0, 0.0, 0.0));
+        let target = Ship::at(Vector3::new(0.0, 10.0, 0.0));
+        let solution = intercept(&shooter, &target, 5.0).expect("sitting duck");
+        assert!(close(Vector3::new(0.0, 1.0, 0.0), solution.aim));
+        assert!((solution.time - 2.0).abs() < 1e-4);
+        assert!(close(target.position(), solution.point));
+    }
+
+    #[test]
+    fn test_leads_moving_target() {
+        let solution = lead(Vector3::new(0.0, 0.0, 0.0), Vector3::new(0.0, 0.0, 0.0),
+                            Vector3::new(10.0, 0.0, 0.0), Vector3::new(0.0, 3.0, 0.0), 5.0)
+            .expect("target is slower than the shot");
+        // 3-4-5 triangle: the shot covers 12.5 while the target moves 7.5
+        assert!((solution.time - 2.5).abs() < 1e-4);
+        assert!(close(Vector3::new(10.0, 7.5, 0.0), solution.point));
+        assert!(close(Vector3::new(0.8, 0.6, 0.0), solution.aim));
+    }
+
+    #[test]
+    fn test_no_solution() {
+        let fleeing = lead(Vector3::new(0.0, 0.0, 0.0), Vector3::new(0.0, 0.0, 0.0),
+                           Vector3::new(10.0, 0.0, 0.0), Vector3::new(6.0, 0.0, 0.0), 5.0);
+        assert_eq!(None, fleeing);
+
+        let matched = lead(Vector3::new(0.0, 0.0, 0.0), Vector3::new(0.0, 0.0, 0.0),
+                           Vector3::new(10.0, 0.0, 0.0), Vector3::new(5.0, 0.0, 0.0), 5.0);
+        assert_eq!(None, matched, "same speed straight away never closes");
+    }
+
+    #[test]
+    fn test_shooter_velocity_is_inherited() {
+        // both drifting together looks the same as both sitting still
+        let drift = Vector3::new(3.0, -2.0, 1.0);
+        let solution = lead(Vector3::new(0.0, 0.0, 0.0), drift,
+                            Vector3::new(0.0, 0.0, 10.0), drift, 10.0).unwrap();
+        assert!(close(Vector3::new(0.0, 0.0, 1.0), solution.aim));
+        assert!((solution.time - 1.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn test_turn_toward() {
+        let ship = Ship::at_origin(); // nose along +x
+        let torque = turn_toward(&ship, Vector3::new(0.0, 1.0, 0.0));
+        assert!(torque.z > 0.0, "turning left is a positive spin around z");
+        assert!(torque.norm() <= MAX_TORQUE + 1e-4);
+
+        assert!(close(Vector3::new(0.0, 0.0, 0.0), turn_toward(&ship, ship.heading())));
+        assert!(turn_toward(&ship, -ship.heading()).norm() > 0.0, "dead astern still turns");
+    }
+
+    #[test]
+    fn test_steer_brakes() {
+        let facing = Rotation3::new(Vector3::new(0.0, 0.0, FRAC_PI_2)); // nose along +y
+        let spinning = Vector3::new(0.0, 0.0, 5.0);
+        let torque = steer(facing, spinning, Vector3::new(0.0, 1.0, 0.0));
+        assert!(torque.z < 0.0, "already on target, so spinning gets damped");
+    }
+}
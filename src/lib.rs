@@ -6,11 +6,19 @@ extern crate glutin;
 extern crate nalgebra as na;
 extern crate ncollide;
 extern crate nphysics3d;
+extern crate rand;
+extern crate rhai;
 extern crate time;
+extern crate toml;
+extern crate wasmi;
+
+#[cfg(test)]
+extern crate wat;
 
 #[macro_use]
 extern crate serde_derive;
 extern crate bincode;
+extern crate serde_json;
 
 // Tokio network stack
 extern crate bytes;